@@ -0,0 +1,117 @@
+use std::ffi::OsStr;
+
+use serde::Serialize;
+
+use crate::prelude::*;
+
+/// Find the records of `plugin` that are also defined by any of its masters.
+///
+/// Every master is loaded in its entirety, which can be slow for large masters.
+///
+pub fn find_master_conflicts(
+    plugin: &PluginData,
+    plugin_name: &str,
+    master_path: &Path,
+    master_name: &str,
+) -> Result<Vec<Conflict>> {
+    let _guard = set_log_level(Level::WARN);
+
+    let mut definitions: HashMap<RecordKey, Vec<&str>> = plugin
+        .record_keys(plugin_name)
+        .into_iter()
+        .map(|key| (key, Vec::new()))
+        .collect();
+
+    let mut path = master_path.to_owned();
+
+    for (name, _) in &plugin.header.masters {
+        path.set_file_name(name);
+
+        let master = PluginData::from_path(&path)?;
+
+        for key in master.record_keys(name) {
+            if let Some(masters) = definitions.get_mut(&key) {
+                masters.push(name);
+            }
+        }
+    }
+
+    let conflicts = definitions
+        .into_iter()
+        .filter_map(|(key, masters)| {
+            let last = masters.last()?;
+            let overrides_other_master = !last.eq_ignore_ascii_case(master_name);
+            (overrides_other_master || masters.len() > 1).then(|| Conflict {
+                key,
+                masters: masters.into_iter().map_into().collect(),
+                overrides_other_master,
+            })
+        })
+        .sorted_unstable_by(|a, b| a.key.cmp(&b.key))
+        .collect();
+
+    Ok(conflicts)
+}
+
+/// The records of a load order that are defined by more than one file.
+///
+#[derive(Serialize)]
+pub struct ConflictMatrix {
+    /// The files of the load order, in load order.
+    pub files: Vec<String>,
+    pub conflicts: Vec<LoadOrderConflict>,
+}
+
+#[derive(Serialize)]
+pub struct LoadOrderConflict {
+    pub key: RecordKey,
+    /// Indices into `ConflictMatrix::files` of each file defining the record, in load order.
+    pub files: Vec<usize>,
+}
+
+impl LoadOrderConflict {
+    /// The index of the file whose definition of the record is used by the game.
+    ///
+    pub fn winner(&self) -> usize {
+        *self.files.last().expect("conflicts have at least two files")
+    }
+}
+
+/// Find all records of the load order that are overridden by a later file.
+///
+/// The `paths` must be given in load order.
+///
+pub fn find_load_order_conflicts(paths: &[PathBuf]) -> Result<ConflictMatrix> {
+    let _guard = set_log_level(Level::WARN);
+
+    let files: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            let name = path.file_name().and_then(OsStr::to_str);
+            name.map(str::to_owned).with_context(|| path.display().to_string())
+        })
+        .collect::<Result<_>>()?;
+
+    let keys: Vec<Vec<RecordKey>> = paths
+        .par_iter()
+        .zip(&files)
+        .map(|(path, name)| -> Result<_> { Ok(PluginData::from_path(path)?.record_keys(name)) })
+        .collect::<Result<_>>()?;
+
+    let mut definitions: HashMap<RecordKey, Vec<usize>> = HashMap::new();
+
+    for (i, keys) in keys.into_iter().enumerate() {
+        for key in keys {
+            definitions.entry(key).or_default().push(i);
+        }
+    }
+
+    let conflicts = definitions
+        .into_iter()
+        .filter(|(_, files)| files.len() > 1)
+        .map(|(key, files)| LoadOrderConflict { key, files })
+        .sorted_unstable_by(|a, b| a.key.cmp(&b.key))
+        .collect();
+
+    Ok(ConflictMatrix { files, conflicts })
+}
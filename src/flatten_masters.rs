@@ -0,0 +1,68 @@
+use std::ffi::OsStr;
+
+use tes3::esp::FileType;
+
+use crate::prelude::*;
+
+/// Flatten the given masters into a single master that has no masters of its own.
+///
+/// The `master_paths` must be given in load order, and every master must only depend on
+/// masters that precede it in the list.
+///
+pub fn flatten_masters(master_paths: &[PathBuf]) -> Result<PluginData> {
+    let [first_path, master_paths @ ..] = master_paths else {
+        bail!("No masters to flatten.");
+    };
+
+    let mut flattened = PluginData::from_path(first_path)?;
+
+    // The references of the first master keep their indices.
+    let mut flattened_masters = vec![(file_name(first_path)?.to_owned(), RefrIndexRemap::new())];
+
+    ensure_masters_flattened(&flattened, first_path, &flattened_masters)?;
+
+    for master_path in master_paths {
+        info!("Flattening master: {}", master_path.display());
+
+        let mut master = PluginData::from_path(master_path)?;
+
+        ensure_masters_flattened(&master, master_path, &flattened_masters)?;
+
+        let refr_remap = master.remap_masters_flattened(&flattened, &flattened_masters);
+        master.remap_textures(&mut flattened)?;
+        master.merge_into(&mut flattened);
+
+        flattened_masters.push((file_name(master_path)?.to_owned(), refr_remap));
+    }
+
+    flattened.header.masters.clear();
+    flattened.header.file_type = FileType::Esm;
+
+    Ok(flattened)
+}
+
+fn file_name(path: &Path) -> Result<&str> {
+    let Some(name) = path.file_name().and_then(OsStr::to_str) else {
+        bail!("Invalid master path: {}", path.display());
+    };
+    Ok(name)
+}
+
+fn ensure_masters_flattened(
+    master: &PluginData,
+    master_path: &Path,
+    flattened_masters: &[(String, RefrIndexRemap)],
+) -> Result<()> {
+    for (name, _) in &master.header.masters {
+        if !flattened_masters
+            .iter()
+            .any(|(flattened, _)| flattened.eq_ignore_ascii_case(name))
+        {
+            bail!(
+                "{} depends on '{name}', which must precede it in the list of masters.",
+                master_path.display()
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use tes3::esp::{AiPackage, TES3Object, TravelDestination};
+
+use crate::prelude::*;
+
+impl PluginData {
+    /// Move every exterior of this plugin by `(dx, dy)` cells.
+    ///
+    /// References move with their cells. Door destinations, travel destinations and AI packages
+    /// that target the moved exteriors are updated to match, wherever they are defined.
+    ///
+    /// Fails if any of the exteriors contain references owned by masters, as those cannot move.
+    ///
+    pub fn offset_exteriors(&mut self, (dx, dy): (i32, i32)) -> Result<()> {
+        if (dx, dy) == (0, 0) {
+            return Ok(());
+        }
+
+        for (coords, exterior) in &self.cells.exteriors {
+            if let Some(cell) = &exterior.cell
+                && cell.references.keys().any(|&(mast_index, _)| mast_index != 0)
+            {
+                bail!("Cannot offset exterior {coords:?}, it contains references owned by masters.");
+            }
+        }
+
+        let moved: HashSet<(i32, i32)> = self.cells.exteriors.keys().copied().collect();
+
+        #[allow(clippy::cast_precision_loss)]
+        let translate = |[x, y, z]: [f32; 3]| [x + dx as f32 * CELL_SIZE, y + dy as f32 * CELL_SIZE, z];
+
+        // Only translations within the moved exteriors are translated.
+        let offset = |translation: &mut [f32; 3]| {
+            if moved.contains(&grid_coords(*translation)) {
+                *translation = translate(*translation);
+            }
+        };
+        let offset_destination = |destination: &mut TravelDestination| {
+            if destination.cell.is_empty() {
+                offset(&mut destination.translation);
+            }
+        };
+
+        self.cells.exteriors = std::mem::take(&mut self.cells.exteriors)
+            .into_iter()
+            .map(|((x, y), mut exterior)| {
+                let coords = (x + dx, y + dy);
+                info!("Offsetting exterior: {:?} -> {coords:?}", (x, y));
+                if let Some(cell) = &mut exterior.cell {
+                    cell.data.grid = coords;
+                    for reference in cell.references.values_mut() {
+                        reference.translation = translate(reference.translation);
+                        if let Some((x, y)) = &mut reference.moved_cell {
+                            *x += dx;
+                            *y += dy;
+                        }
+                    }
+                }
+                if let Some(landscape) = &mut exterior.landscape {
+                    landscape.grid = coords;
+                }
+                if let Some(pathgrid) = &mut exterior.pathgrid {
+                    pathgrid.data.grid = coords;
+                }
+                (coords, exterior)
+            })
+            .collect();
+
+        for cell in self.cells.iter_mut() {
+            for reference in cell.references.values_mut() {
+                if let Some(destination) = &mut reference.destination {
+                    offset_destination(destination);
+                }
+            }
+        }
+
+        for object in self.objects.values_mut() {
+            let (travel_destinations, ai_packages) = match object {
+                TES3Object::Npc(npc) => (&mut npc.travel_destinations, &mut npc.ai_packages),
+                TES3Object::Creature(creature) => (&mut creature.travel_destinations, &mut creature.ai_packages),
+                _ => continue,
+            };
+            travel_destinations.iter_mut().for_each(&offset_destination);
+            for package in ai_packages {
+                match package {
+                    AiPackage::Travel(package) => offset(&mut package.location),
+                    AiPackage::Escort(package) if package.cell.is_empty() => offset(&mut package.location),
+                    AiPackage::Follow(package) if package.cell.is_empty() => offset(&mut package.location),
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use serde::Serialize;
+
+use crate::prelude::*;
+
+/// How to merge a plugin whose edits depend on masters that follow the merge target.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LaterMasters {
+    /// Refuse to merge, reporting the records that cause the dependencies.
+    #[default]
+    Refuse,
+    /// Add the later masters to the masters list of the merged master.
+    Carry,
+}
+
+/// A record of the plugin that depends on a master that follows the merge target.
+///
+#[derive(Serialize)]
+pub struct LaterMasterDependency {
+    /// The later master that is depended upon.
+    pub master: String,
+    /// The record of the plugin that causes the dependency.
+    pub record: RecordKey,
+    /// The id of the later master's object or interior that is used by the record, if any.
+    pub uses: Option<ObjectId>,
+}
+
+/// Resolve the masters that follow `master_name` in the plugin's masters list.
+///
+/// If the plugin does not depend on any of them they are removed from its masters list.
+/// Otherwise the dependencies are handled as specified by `policy`.
+///
+/// Every field of the plugin that refers to an object or interior is checked, see `VisitIds`.
+///
+/// Only records that a later master introduces are dependencies. Records that it overrides
+/// from the merge target, or from a master before it, are already available without it.
+///
+pub fn resolve_later_masters(
+    plugin: &mut PluginData,
+    plugin_name: &str,
+    master: &PluginData,
+    master_path: &Path,
+    master_name: &str,
+    policy: LaterMasters,
+) -> Result<Vec<LaterMasterDependency>> {
+    let Some(position) = plugin
+        .header
+        .masters
+        .iter()
+        .position(|(name, _)| name.eq_ignore_ascii_case(master_name))
+    else {
+        bail!("Merge target is missing from plugin's master list.");
+    };
+
+    let later_masters = plugin.header.masters[position + 1..]
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect_vec();
+
+    if later_masters.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut dependencies = Vec::new();
+    let mut circular = Vec::new();
+
+    let plugin_keys = plugin.record_keys(plugin_name);
+    let plugin_uses = plugin.uses(plugin_name);
+
+    // The ids of objects and interiors that are defined by the plugin, or before the later master.
+    let mut defined: HashSet<UString> = defined_ids(plugin).chain(defined_ids(master)).collect();
+
+    // The records that are defined before the later master, its references are checked separately.
+    let mut earlier_keys: HashSet<RecordKey> = non_reference_keys(master, master_name).collect();
+
+    let mut path = master_path.to_owned();
+
+    // Only the cells and dialogues of the masters preceding the merge target have been loaded.
+    for (earlier_name, _) in &plugin.header.masters[..position] {
+        path.set_file_name(earlier_name);
+
+        let earlier = {
+            let _guard = set_log_level(Level::WARN);
+            PluginData::from_path(&path)?
+        };
+
+        defined.extend(defined_ids(&earlier));
+        earlier_keys.extend(non_reference_keys(&earlier, earlier_name));
+    }
+
+    for later_name in &later_masters {
+        path.set_file_name(later_name);
+
+        let later = {
+            let _guard = set_log_level(Level::WARN);
+            PluginData::from_path(&path)?
+        };
+
+        if later
+            .header
+            .masters
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case(master_name))
+        {
+            circular.push(later_name.as_str());
+        }
+
+        let owner = later_name.to_ascii_lowercase();
+        let later_keys: HashSet<_> = later.record_keys(later_name).into_iter().collect();
+
+        // Records introduced by the later master that are edited by the plugin.
+        for key in &plugin_keys {
+            let owned = matches!(key, RecordKey::Reference { owner: o, .. } if *o == owner);
+            if owned || (later_keys.contains(key) && !earlier_keys.contains(key)) {
+                dependencies.push(LaterMasterDependency {
+                    master: later_name.clone(),
+                    record: key.clone(),
+                    uses: None,
+                });
+            }
+        }
+
+        // Objects and interiors introduced by the later master that are used by the plugin.
+        let later_ids: HashSet<UString> = defined_ids(&later).filter(|id| !defined.contains(id)).collect();
+        let later_uses = plugin_uses
+            .iter()
+            .filter(|(id, _)| later_ids.contains(id.as_uncased()))
+            .map(|(id, usage)| (usage.key.clone(), id.clone()))
+            .unique();
+        for (record, id) in later_uses {
+            dependencies.push(LaterMasterDependency {
+                master: later_name.clone(),
+                record,
+                uses: Some(id),
+            });
+        }
+
+        defined.extend(later_ids);
+        earlier_keys.extend(non_reference_keys(&later, later_name));
+    }
+
+    if dependencies.is_empty() {
+        info!("Removing unused later masters: {}", later_masters.join(", "));
+        plugin.remove_masters(&later_masters);
+        return Ok(dependencies);
+    }
+
+    for dependency in &dependencies {
+        match &dependency.uses {
+            Some(id) => warn!(
+                "{} uses '{id}' of later master {}",
+                dependency.record, dependency.master
+            ),
+            None => warn!(
+                "{} edits a record of later master {}",
+                dependency.record, dependency.master
+            ),
+        }
+    }
+
+    match policy {
+        LaterMasters::Refuse => {
+            bail!(
+                "Plugin depends on {} record(s) of masters that follow the merge target: {}",
+                dependencies.len(),
+                dependencies
+                    .iter()
+                    .map(|dependency| dependency.record.to_string())
+                    .join("; ")
+            );
+        }
+        LaterMasters::Carry if !circular.is_empty() => {
+            bail!(
+                "Cannot add later masters that depend on the merge target: {}",
+                circular.join(", ")
+            );
+        }
+        LaterMasters::Carry => {
+            info!("Carrying later masters: {}", later_masters.join(", "));
+        }
+    }
+
+    Ok(dependencies)
+}
+
+/// The ids of the objects and interiors that are defined by the given plugin.
+///
+fn defined_ids(plugin: &PluginData) -> impl Iterator<Item = UString> + '_ {
+    let objects = plugin.objects.keys().map(|(_, id)| UString::from(id.clone()));
+    objects.chain(plugin.cells.interiors.keys().cloned())
+}
+
+/// The keys of the records of the given plugin, excluding its references.
+///
+fn non_reference_keys(plugin: &PluginData, file_name: &str) -> impl Iterator<Item = RecordKey> {
+    let keys = plugin.record_keys(file_name).into_iter();
+    keys.filter(|key| !matches!(key, RecordKey::Reference { .. }))
+}
+
+impl PluginData {
+    /// Remove the given masters from the masters list, shifting reference indices to match.
+    ///
+    /// Any references that belong to the removed masters must have been removed beforehand.
+    ///
+    fn remove_masters(&mut self, names: &[String]) {
+        let mut index_remap = vec![0];
+        let mut masters = Vec::with_capacity(self.header.masters.len());
+
+        for master in std::mem::take(&mut self.header.masters) {
+            if names.iter().any(|name| name.eq_ignore_ascii_case(&master.0)) {
+                index_remap.push(0);
+            } else {
+                masters.push(master);
+                index_remap.push(masters.len().try_into().unwrap());
+            }
+        }
+
+        self.header.masters = masters;
+
+        for cell in self.cells.iter_mut() {
+            cell.references = std::mem::take(&mut cell.references)
+                .into_iter()
+                .map(|((mast_index, refr_index), mut reference)| {
+                    let mast_index = index_remap[mast_index as usize];
+                    reference.mast_index = mast_index;
+                    ((mast_index, refr_index), reference)
+                })
+                .collect();
+        }
+    }
+}
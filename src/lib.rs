@@ -1,18 +1,45 @@
 mod backup;
 pub use backup::*;
 
+mod conflicts;
+pub use conflicts::*;
+
+mod flatten_masters;
+pub use flatten_masters::*;
+
+mod grid_offset;
+pub use grid_offset::*;
+
+mod later_masters;
+pub use later_masters::*;
+
 mod logging;
 pub use logging::*;
 
+mod manifest;
+pub use manifest::*;
+
+mod moved_references;
+pub use moved_references::*;
+
 mod merge_plugins;
 pub use merge_plugins::*;
 
+mod remove_unused;
+pub use remove_unused::*;
+
+mod replace_ids;
+pub use replace_ids::*;
+
 mod traits;
 pub use traits::*;
 
 mod types;
 pub use types::*;
 
+mod usage_index;
+pub use usage_index::*;
+
 pub mod prelude {
     pub use super::*;
 
@@ -1,6 +1,6 @@
 use merge_to_master::prelude::*;
 
-use clap::{Arg, ArgAction, command};
+use clap::{Arg, ArgAction, ArgGroup, ArgMatches, Command, command};
 
 #[global_allocator]
 static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
@@ -8,6 +8,8 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 fn main() -> Result<()> {
     let matches = command!()
         .arg_required_else_help(true)
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
         .args(&[
             Arg::new("PLUGIN")
                 .help("The plugin that will be merged into <MASTER>.")
@@ -22,6 +24,52 @@ fn main() -> Result<()> {
                 .long("remove-deleted")
                 .short('r')
                 .action(ArgAction::SetTrue),
+            Arg::new("CLEAN-INFOS")
+                .help("How to handle INFOs that refer to deleted objects: clean, remove, or report. [default: clean]")
+                .long("clean-infos")
+                .value_name("MODE")
+                .value_parser(str::parse::<DeletedInfoReferences>)
+                .requires("REMOVE-DELETED"),
+            Arg::new("FALLBACK-RACE")
+                .help("The race given to NPCs whose race was deleted.")
+                .long("fallback-race")
+                .value_name("ID")
+                .requires("REMOVE-DELETED"),
+            Arg::new("FALLBACK-CLASS")
+                .help("The class given to NPCs whose class was deleted.")
+                .long("fallback-class")
+                .value_name("ID")
+                .requires("REMOVE-DELETED"),
+            Arg::new("FALLBACK-TEXTURE")
+                .help("The landscape texture that replaces deleted textures in landscapes.")
+                .long("fallback-texture")
+                .value_name("ID")
+                .requires("REMOVE-DELETED"),
+            Arg::new("SAFE-DELETE")
+                .help("Disable and sink deleted references of other masters instead of deleting them.")
+                .long("safe-delete")
+                .requires("REMOVE-DELETED")
+                .action(ArgAction::SetTrue),
+            Arg::new("DEDUPE-TEXTURES")
+                .help("Unify landscape textures that use the same texture file.")
+                .long("dedupe-textures")
+                .action(ArgAction::SetTrue),
+            Arg::new("REMOVE-UNUSED")
+                .help("Remove unused textures, enchantments and body parts, and the sound gens of deleted creatures.")
+                .long("remove-unused")
+                .action(ArgAction::SetTrue),
+            Arg::new("REPLACE-ID")
+                .help("Redirect all uses of the object OLD to the object NEW.")
+                .long("replace-id")
+                .value_name("OLD=NEW")
+                .value_parser(id_replacement)
+                .action(ArgAction::Append),
+            Arg::new("RENAME-INTERIOR")
+                .help("Rename the interior OLD to NEW, updating everything that refers to it.")
+                .long("rename-interior")
+                .value_name("OLD=NEW")
+                .value_parser(interior_rename)
+                .action(ArgAction::Append),
             Arg::new("OVERWRITE")
                 .help("Overwrite <MASTER> without creating a backup.")
                 .long("overwrite")
@@ -31,37 +79,339 @@ fn main() -> Result<()> {
                 .help("Preserve duplicate references, if not specified duplicates will be removed.")
                 .long("preserve-duplicate-references")
                 .action(ArgAction::SetTrue),
+            Arg::new("DUPLICATE-TOLERANCE")
+                .help("The maximum difference between transforms of duplicate references. [default: 0.00001]")
+                .long("duplicate-tolerance")
+                .value_name("TOLERANCE")
+                .value_parser(clap::value_parser!(f32))
+                .conflicts_with("PRESERVE-DUPLICATE-REFERENCES"),
+            Arg::new("IGNORE-DUPLICATE-SCALE")
+                .help("Also treat references with different scales as duplicates.")
+                .long("ignore-duplicate-scale")
+                .conflicts_with("PRESERVE-DUPLICATE-REFERENCES")
+                .action(ArgAction::SetTrue),
+            Arg::new("COMPARE-DUPLICATE-DATA")
+                .help("Only treat references with equal ownership and lock data as duplicates.")
+                .long("compare-duplicate-data")
+                .conflicts_with("PRESERVE-DUPLICATE-REFERENCES")
+                .action(ArgAction::SetTrue),
+            Arg::new("CROSS-BORDER-DUPLICATES")
+                .help("How to handle duplicates in neighboring exteriors: off, report, or remove. [default: off]")
+                .long("cross-border-duplicates")
+                .value_name("MODE")
+                .value_parser(str::parse::<CrossBorder>),
+            Arg::new("BORDER-DISTANCE")
+                .help("The distance from cell borders within which references are compared. [default: 128]")
+                .long("border-distance")
+                .value_name("UNITS")
+                .value_parser(clap::value_parser!(f32))
+                .requires("CROSS-BORDER-DUPLICATES"),
+            Arg::new("MOVED-REFERENCES")
+                .help("How to handle 'moved references': keep, apply, or drop. [default: keep]")
+                .long("moved-references")
+                .value_name("MODE")
+                .value_parser(str::parse::<MovedReferences>),
             Arg::new("APPLY-MOVED-REFERENCES")
-                .help("Put 'moved references' into their the new cell's reference list. (Experimental)")
+                .help("Same as `--moved-references apply`.")
                 .long("apply-moved-references")
+                .conflicts_with("MOVED-REFERENCES")
+                .action(ArgAction::SetTrue),
+            Arg::new("RELOCATE-REFERENCES")
+                .help("Put references into the exterior cell that contains them.")
+                .long("relocate-references")
                 .action(ArgAction::SetTrue),
+            Arg::new("CARRY-LATER-MASTERS")
+                .help("Add masters that follow <MASTER> to its masters list if <PLUGIN> depends on them.")
+                .long("carry-later-masters")
+                .action(ArgAction::SetTrue),
+            Arg::new("GRID-OFFSET")
+                .help("Move the exteriors of <PLUGIN> by the given number of cells before merging.")
+                .long("grid-offset")
+                .value_name("DX,DY")
+                .value_parser(into_grid_offset)
+                .allow_hyphen_values(true),
+            Arg::new("INCLUDE-TAG")
+                .help("Only merge records with the given tag. (e.g. LAND)")
+                .long("include-tag")
+                .value_name("TAG")
+                .action(ArgAction::Append),
+            Arg::new("EXCLUDE-TAG")
+                .help("Do not merge records with the given tag. (e.g. NPC_)")
+                .long("exclude-tag")
+                .value_name("TAG")
+                .action(ArgAction::Append),
+            Arg::new("INCLUDE-ID")
+                .help("Only merge objects with ids matching the given glob, or regex if enclosed in slashes.")
+                .long("include-id")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("EXCLUDE-ID")
+                .help("Do not merge objects with ids matching the given glob, or regex if enclosed in slashes.")
+                .long("exclude-id")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("INCLUDE-INTERIOR")
+                .help("Only merge interiors with names matching the given glob, or regex if enclosed in slashes.")
+                .long("include-interior")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("EXCLUDE-INTERIOR")
+                .help("Do not merge interiors with names matching the given glob, or regex if enclosed in slashes.")
+                .long("exclude-interior")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("INCLUDE-EXTERIOR")
+                .help("Only merge exteriors within the given rectangle of cell coordinates.")
+                .long("include-exterior")
+                .value_name("X1,Y1,X2,Y2")
+                .value_parser(into_grid_rect)
+                .action(ArgAction::Append),
+            Arg::new("EXCLUDE-EXTERIOR")
+                .help("Do not merge exteriors within the given rectangle of cell coordinates.")
+                .long("exclude-exterior")
+                .value_name("X1,Y1,X2,Y2")
+                .value_parser(into_grid_rect)
+                .action(ArgAction::Append),
+            Arg::new("INCLUDE-TOPIC")
+                .help("Only merge dialogue topics matching the given glob, or regex if enclosed in slashes.")
+                .long("include-topic")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("EXCLUDE-TOPIC")
+                .help("Do not merge dialogue topics matching the given glob, or regex if enclosed in slashes.")
+                .long("exclude-topic")
+                .value_name("PATTERN")
+                .value_parser(id_pattern)
+                .action(ArgAction::Append),
+            Arg::new("RESIDUAL")
+                .help("Save the contents excluded by filters as a new plugin that depends on <MASTER>.")
+                .long("residual")
+                .value_name("FILE")
+                .value_parser(into_path)
+                .requires("FILTER"),
+            Arg::new("REMERGE")
+                .help("Remove the contents added by a previous version of <PLUGIN> before merging.")
+                .long("remerge")
+                .action(ArgAction::SetTrue),
+            Arg::new("ANNOTATE-HEADER")
+                .help("Note the merged plugin in the description of <MASTER>.")
+                .long("annotate-header")
+                .action(ArgAction::SetTrue),
+            Arg::new("DRY-RUN")
+                .help("Show what would be changed without saving anything.")
+                .long("dry-run")
+                .action(ArgAction::SetTrue),
+            Arg::new("REPORT-CONFLICTS")
+                .help("Report records of <PLUGIN> that are also defined by its other masters.")
+                .long("report-conflicts")
+                .action(ArgAction::SetTrue),
+            Arg::new("REPORT")
+                .help("Write a report of the merge to the given JSON file.")
+                .long("report")
+                .value_name("FILE")
+                .value_parser(into_path),
         ])
+        .group(ArgGroup::new("FILTER").multiple(true).args([
+            "INCLUDE-TAG",
+            "EXCLUDE-TAG",
+            "INCLUDE-ID",
+            "EXCLUDE-ID",
+            "INCLUDE-INTERIOR",
+            "EXCLUDE-INTERIOR",
+            "INCLUDE-EXTERIOR",
+            "EXCLUDE-EXTERIOR",
+            "INCLUDE-TOPIC",
+            "EXCLUDE-TOPIC",
+        ]))
+        .subcommand(
+            Command::new("conflicts")
+                .about("Show which records of a load order are overridden by which files.")
+                .args(&[
+                    Arg::new("PLUGINS")
+                        .help("The plugins of the load order, in load order.")
+                        .value_parser(into_file_path)
+                        .num_args(1..)
+                        .required(true),
+                    Arg::new("REPORT")
+                        .help("Write the conflicts to the given JSON file.")
+                        .long("report")
+                        .value_name("FILE")
+                        .value_parser(into_path),
+                ]),
+        )
+        .subcommand(
+            Command::new("flatten")
+                .about("Flatten a list of masters into a single master that has no masters of its own.")
+                .args(&[
+                    Arg::new("MASTERS")
+                        .help("The masters that will be flattened, in load order.")
+                        .value_parser(into_file_path)
+                        .num_args(1..)
+                        .required(true),
+                    Arg::new("OUTPUT")
+                        .help("The path where the flattened master will be saved.")
+                        .long("output")
+                        .short('o')
+                        .value_name("FILE")
+                        .value_parser(into_path)
+                        .required(true),
+                ]),
+        )
+        .subcommand(
+            Command::new("history")
+                .about("Show the history of plugins merged into a master.")
+                .arg(
+                    Arg::new("MASTER")
+                        .help("The master whose history will be shown.")
+                        .value_parser(into_file_path)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("uses")
+                .about("List the records of a plugin that refer to an object.")
+                .args(&[
+                    Arg::new("FILE")
+                        .help("The plugin to search.")
+                        .value_parser(into_file_path)
+                        .required(true),
+                    Arg::new("ID")
+                        .help("The id of the object.")
+                        .required(true),
+                ]),
+        )
         .get_matches();
 
+    match matches.subcommand() {
+        Some(("conflicts", matches)) => conflicts(matches),
+        Some(("flatten", matches)) => flatten(matches),
+        Some(("history", matches)) => history(matches),
+        Some(("uses", matches)) => uses(matches),
+        _ => merge(&matches),
+    }
+}
+
+fn merge(matches: &ArgMatches) -> Result<()> {
     // files
     let plugin_path = matches.get_one("PLUGIN").unwrap();
     let master_path = matches.get_one("MASTER").unwrap();
 
     // flags
     let overwrite = matches.get_flag("OVERWRITE");
+    let dry_run = matches.get_flag("DRY-RUN");
     let remove_deleted = matches.get_flag("REMOVE-DELETED");
-    let apply_moved_references = matches.get_flag("APPLY-MOVED-REFERENCES");
+    let clean = CleanOptions {
+        infos: matches.get_one("CLEAN-INFOS").copied().unwrap_or_default(),
+        fallback_race: matches.get_one("FALLBACK-RACE").cloned(),
+        fallback_class: matches.get_one("FALLBACK-CLASS").cloned(),
+        fallback_texture: matches.get_one("FALLBACK-TEXTURE").cloned(),
+        safe_delete: matches.get_flag("SAFE-DELETE"),
+        remove_sound_gens: false,
+    };
+    let dedupe_textures = matches.get_flag("DEDUPE-TEXTURES");
+    let grid_offset = matches.get_one("GRID-OFFSET").copied().unwrap_or_default();
+    let remove_unused = matches.get_flag("REMOVE-UNUSED");
+    let moved_references = if matches.get_flag("APPLY-MOVED-REFERENCES") {
+        MovedReferences::Apply
+    } else {
+        matches.get_one("MOVED-REFERENCES").copied().unwrap_or_default()
+    };
+    let relocate_references = matches.get_flag("RELOCATE-REFERENCES");
+    let duplicate_references = DuplicateReferences {
+        tolerance: matches
+            .get_one("DUPLICATE-TOLERANCE")
+            .copied()
+            .unwrap_or(DuplicateReferences::DEFAULT.tolerance),
+        compare_scale: !matches.get_flag("IGNORE-DUPLICATE-SCALE"),
+        compare_data: matches.get_flag("COMPARE-DUPLICATE-DATA"),
+        cross_border: matches.get_one("CROSS-BORDER-DUPLICATES").copied().unwrap_or_default(),
+        border_distance: matches
+            .get_one("BORDER-DISTANCE")
+            .copied()
+            .unwrap_or(DuplicateReferences::DEFAULT.border_distance),
+    };
     let preserve_duplicate_references = matches.get_flag("PRESERVE-DUPLICATE-REFERENCES");
+    let remerge = matches.get_flag("REMERGE");
+    let annotate_header = matches.get_flag("ANNOTATE-HEADER");
+    let report_conflicts = matches.get_flag("REPORT-CONFLICTS");
+    let later_masters = if matches.get_flag("CARRY-LATER-MASTERS") {
+        LaterMasters::Carry
+    } else {
+        LaterMasters::Refuse
+    };
+    let report_path = matches.get_one::<PathBuf>("REPORT");
+    let residual_path = matches.get_one::<PathBuf>("RESIDUAL");
+
+    // filters
+    let filter = MergeFilter {
+        include: FilterRules {
+            tags: get_values(matches, "INCLUDE-TAG"),
+            ids: get_values(matches, "INCLUDE-ID"),
+            interiors: get_values(matches, "INCLUDE-INTERIOR"),
+            exteriors: get_values(matches, "INCLUDE-EXTERIOR"),
+            topics: get_values(matches, "INCLUDE-TOPIC"),
+        },
+        exclude: FilterRules {
+            tags: get_values(matches, "EXCLUDE-TAG"),
+            ids: get_values(matches, "EXCLUDE-ID"),
+            interiors: get_values(matches, "EXCLUDE-INTERIOR"),
+            exteriors: get_values(matches, "EXCLUDE-EXTERIOR"),
+            topics: get_values(matches, "EXCLUDE-TOPIC"),
+        },
+    };
 
     let (log_path, _guard) = init_logger()?;
 
     info!("Merging plugins...");
 
-    let merged = merge_plugins(
+    let MergeOutput {
+        master: merged,
+        report,
+        residual,
+        manifest_entry,
+    } = merge_plugins_with_report(
         plugin_path,
         master_path,
         MergeOptions {
             remove_deleted,
-            apply_moved_references,
+            replace_ids: get_values(matches, "REPLACE-ID"),
+            rename_interiors: get_values(matches, "RENAME-INTERIOR"),
+            clean,
+            moved_references,
+            relocate_references,
             preserve_duplicate_references,
+            duplicate_references,
+            report_conflicts,
+            later_masters,
+            filter,
+            residual: residual_path.is_some(),
+            remerge,
+            annotate_header,
+            dedupe_textures,
+            remove_unused,
+            grid_offset,
         },
     )?;
 
+    report.log();
+
+    if let Some(report_path) = report_path {
+        info!("Saving report...");
+        report.save_path(report_path)?;
+    }
+
+    if dry_run {
+        print_preview(&report);
+        eprintln!("Dry run, nothing was saved: {}", master_path.display());
+        eprintln!("Log available at: {}", log_path.display());
+        return Ok(());
+    }
+
     if !overwrite {
         info!("Creating backup...");
         if backup(master_path).is_none() {
@@ -73,6 +423,19 @@ fn main() -> Result<()> {
 
     merged.save_path(master_path)?;
 
+    info!("Updating manifest...");
+
+    let mut manifest = MergeManifest::from_master_path(master_path)?;
+    manifest.entries.push(manifest_entry);
+    manifest.save_master_path(master_path)?;
+
+    if let Some((mut residual, residual_path)) = residual.zip(residual_path) {
+        info!("Saving residual plugin...");
+        residual.header.ensure_master_size(master_path)?;
+        residual.save_path(residual_path)?;
+        eprintln!("Residual saved to: {}", residual_path.display());
+    }
+
     info!("Finished!");
 
     eprintln!("Merge Successful: {}", master_path.display());
@@ -81,6 +444,139 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Print the records that were cleaned because they referred to deleted objects.
+///
+fn print_preview(report: &MergeReport) {
+    let cleaned = &report.cleaned;
+
+    for (label, keys) in [
+        ("Deleted", &cleaned.deleted),
+        ("Body part of deleted race", &cleaned.bodyparts),
+        ("Sound gen of deleted creature", &cleaned.sound_gens),
+    ] {
+        for key in keys {
+            println!("{label}: {key}");
+        }
+    }
+
+    let fields = report
+        .cleaned
+        .fields
+        .iter()
+        .into_group_map_by(|field| field.id.to_ascii_lowercase());
+
+    for (id, fields) in fields.into_iter().sorted_unstable_by(|a, b| a.0.cmp(&b.0)) {
+        println!("Deleted '{id}':");
+        for field in fields {
+            println!("    {field}");
+        }
+    }
+
+    for field in &cleaned.fallbacks {
+        println!("{field}");
+    }
+
+    for info in &report.cleaned.infos {
+        println!("INFO {} of topic '{}' ({:?}): {}", info.info, info.topic, info.action, info.fields.join(", "));
+    }
+
+    for textures in &report.cleaned.textures {
+        println!("{textures}");
+    }
+}
+
+fn conflicts(matches: &ArgMatches) -> Result<()> {
+    let plugin_paths = matches.get_many::<PathBuf>("PLUGINS").unwrap().cloned().collect_vec();
+    let report_path = matches.get_one::<PathBuf>("REPORT");
+
+    let matrix = find_load_order_conflicts(&plugin_paths)?;
+
+    for (i, file) in matrix.files.iter().enumerate() {
+        println!("[{i:>3}] {file}");
+    }
+    println!();
+
+    // One column per file: 'o' for overridden definitions, 'W' for the winning definition.
+    for conflict in &matrix.conflicts {
+        let mut row = vec!['.'; matrix.files.len()];
+        for &i in &conflict.files {
+            row[i] = 'o';
+        }
+        row[conflict.winner()] = 'W';
+        println!("{} {}", String::from_iter(row), conflict.key);
+    }
+
+    if let Some(report_path) = report_path {
+        save_json(&matrix, report_path)?;
+    }
+
+    Ok(())
+}
+
+fn flatten(matches: &ArgMatches) -> Result<()> {
+    let master_paths = matches.get_many::<PathBuf>("MASTERS").unwrap().cloned().collect_vec();
+    let output_path = matches.get_one::<PathBuf>("OUTPUT").unwrap();
+
+    let (log_path, _guard) = init_logger()?;
+
+    info!("Flattening masters...");
+
+    let flattened = flatten_masters(&master_paths)?;
+
+    info!("Saving results...");
+
+    flattened.save_path(output_path)?;
+
+    info!("Finished!");
+
+    eprintln!("Flatten Successful: {}", output_path.display());
+    eprintln!("Log available at: {}", log_path.display());
+
+    Ok(())
+}
+
+fn history(matches: &ArgMatches) -> Result<()> {
+    let master_path = matches.get_one::<PathBuf>("MASTER").unwrap();
+
+    let manifest = MergeManifest::from_master_path(master_path)?;
+
+    if manifest.entries.is_empty() {
+        println!("No merges recorded for {}", master_path.display());
+    }
+
+    for entry in &manifest.entries {
+        println!("{entry}");
+    }
+
+    Ok(())
+}
+
+fn uses(matches: &ArgMatches) -> Result<()> {
+    let path = matches.get_one::<PathBuf>("FILE").unwrap();
+    let id = matches.get_one::<String>("ID").unwrap();
+
+    let plugin = PluginData::from_path(path)?;
+    let index = plugin.usage_index(&path.file_name().unwrap_or_default().to_string_lossy());
+
+    let uses = index.get(id);
+    if uses.is_empty() {
+        println!("No uses of '{id}' found in {}", path.display());
+    }
+
+    for usage in uses {
+        println!("{}: {}", usage.record, usage.field);
+    }
+
+    Ok(())
+}
+
+fn get_values<T: Clone + Send + Sync + 'static>(matches: &ArgMatches, id: &str) -> Vec<T> {
+    matches
+        .get_many::<T>(id)
+        .map(|values| values.cloned().collect())
+        .unwrap_or_default()
+}
+
 fn into_file_path(arg: &str) -> Result<PathBuf> {
     let path = PathBuf::from_slash(arg);
     if !path.is_file() {
@@ -88,3 +584,25 @@ fn into_file_path(arg: &str) -> Result<PathBuf> {
     }
     Ok(path)
 }
+
+fn into_path(arg: &str) -> Result<PathBuf> {
+    Ok(PathBuf::from_slash(arg))
+}
+
+fn into_grid_offset(arg: &str) -> Result<(i32, i32)> {
+    let Some((dx, dy)) = arg.split(',').map(|s| s.trim().parse::<i32>()).collect_tuple() else {
+        bail!("Invalid grid offset, expected DX,DY: {arg}");
+    };
+    Ok((dx?, dy?))
+}
+
+fn into_grid_rect(arg: &str) -> Result<GridRect> {
+    let Some((x1, y1, x2, y2)) = arg.split(',').map(|s| s.trim().parse::<i32>()).collect_tuple() else {
+        bail!("Invalid exterior rectangle, expected X1,Y1,X2,Y2: {arg}");
+    };
+    let (x1, y1, x2, y2) = (x1?, y1?, x2?, y2?);
+    Ok(GridRect {
+        min: (x1.min(x2), y1.min(y2)),
+        max: (x1.max(x2), y1.max(y2)),
+    })
+}
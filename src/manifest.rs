@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use tes3::esp::ObjectInfo;
+
+use crate::prelude::*;
+
+/// The history of plugins merged into a master, stored in a file next to the master.
+///
+#[derive(Default, Serialize, Deserialize)]
+pub struct MergeManifest {
+    pub entries: Vec<ManifestEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The file name of the merged plugin.
+    pub plugin: String,
+    /// A hash of the merged plugin's contents, identifying its version.
+    #[serde(default)]
+    pub hash: u64,
+    /// The size of the merged plugin in bytes.
+    #[serde(default)]
+    pub size: u64,
+    /// When the merge happened, in seconds since the Unix epoch.
+    #[serde(default)]
+    pub date: u64,
+    /// The non-default options used for the merge, see `MergeOptions::summary`.
+    #[serde(default)]
+    pub options: Vec<String>,
+    /// Records that were added to the master by the plugin.
+    pub records: Vec<RecordKey>,
+    /// Indices of the references that were added to the master by the plugin.
+    pub references: Vec<u32>,
+}
+
+impl MergeManifest {
+    /// The path of the manifest for the master at `master_path`.
+    ///
+    /// (e.g. `"Master.esm"` -> `"Master.esm.manifest.json"`)
+    ///
+    pub fn path(master_path: &Path) -> PathBuf {
+        let mut file_name = master_path.file_name().unwrap_or_default().to_owned();
+        file_name.push(".manifest.json");
+        master_path.with_file_name(file_name)
+    }
+
+    /// Load the manifest for the master at `master_path`, or an empty one if it does not exist.
+    ///
+    pub fn from_master_path(master_path: &Path) -> Result<Self> {
+        let path = Self::path(master_path);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let file = std::fs::File::open(&path).with_context(|| path.display().to_string())?;
+        serde_json::from_reader(std::io::BufReader::new(file)) //
+            .with_context(|| path.display().to_string())
+    }
+
+    pub fn save_master_path(&self, master_path: &Path) -> Result<()> {
+        save_json(self, &Self::path(master_path))
+    }
+
+    /// The most recent entry for the plugin with the given name.
+    ///
+    pub fn latest(&self, plugin_name: &str) -> Option<&ManifestEntry> {
+        self.entries
+            .iter()
+            .rfind(|entry| entry.plugin.eq_ignore_ascii_case(plugin_name))
+    }
+
+    /// The reference index following those of every reference added by a merge.
+    ///
+    /// References of previous merges may since have been removed as stale, but their indices \
+    /// must never be reused. Savegames still refer to those references by their index.
+    ///
+    pub fn next_reference_index(&self) -> u32 {
+        self.entries
+            .iter()
+            .flat_map(|entry| &entry.references)
+            .max()
+            .map_or(1, |i| i + 1)
+    }
+}
+
+impl ManifestEntry {
+    /// Create an entry for merging `plugin` into `master`.
+    ///
+    /// Both must have already been prepared for merging, see `merge_plugins`. If this plugin \
+    /// was merged previously then any of its still present records are carried over.
+    ///
+    pub fn new(
+        plugin: &PluginData,
+        plugin_name: &str,
+        plugin_path: &Path,
+        master: &PluginData,
+        options: &MergeOptions,
+        previous: Option<&ManifestEntry>,
+    ) -> Result<Self> {
+        let previous_records: HashSet<_> = previous.iter().flat_map(|entry| &entry.records).collect();
+
+        let records = plugin
+            .record_keys(plugin_name)
+            .into_iter()
+            .filter(|key| !matches!(key, RecordKey::Reference { .. }))
+            .filter(|key| previous_records.contains(key) || !master.contains_record(key))
+            .sorted_unstable()
+            .collect();
+
+        // Newly added references are the local references that do not exist in the master.
+        let references = plugin
+            .cells
+            .iter_keyed()
+            .flat_map(|(cell_key, cell)| {
+                let master_cell = master.cells.get_cell(&cell_key);
+                cell.references.keys().filter(move |key| {
+                    key.0 == 0 && !master_cell.is_some_and(|master_cell| master_cell.references.contains_key(*key))
+                })
+            })
+            .map(|&(_, refr_index)| refr_index)
+            .sorted_unstable()
+            .collect();
+
+        let date = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        Ok(Self {
+            plugin: plugin_name.into(),
+            hash: hash_file(plugin_path)?,
+            size: plugin_path.metadata()?.len(),
+            date,
+            options: options.summary(),
+            records,
+            references,
+        })
+    }
+
+    /// A short description of this merge, suitable for a header description.
+    ///
+    /// (e.g. `"Merged Plugin.esp (2024-01-31)"`)
+    ///
+    pub fn annotation(&self) -> String {
+        let date = format_date(self.date);
+        format!("Merged {} ({})", self.plugin, &date[..10])
+    }
+
+    /// Remove the contents added by this entry's plugin whose keys are not in `current`.
+    ///
+    /// All references added by the entry are removed, as the new version of the plugin will \
+    /// add its own. Changes made to records that were not added by the plugin are retained.
+    ///
+    /// The `current` keys must include any records of the plugin that are excluded from the \
+    /// merge by a filter, as those were not removed from the plugin.
+    ///
+    pub fn remove_stale(&self, master: &mut PluginData, current: &[RecordKey]) {
+        let current: HashSet<_> = current.iter().collect();
+
+        for key in &self.records {
+            if !current.contains(key) && master.remove_record(key) {
+                info!("Removed stale record: {key}");
+            }
+        }
+
+        master
+            .cells
+            .exteriors
+            .retain(|_, exterior| exterior.count_objects() != 0);
+        master
+            .cells
+            .interiors
+            .retain(|_, interior| interior.count_objects() != 0);
+
+        let references: HashSet<_> = self.references.iter().copied().collect();
+
+        for cell in master.cells.iter_mut() {
+            cell.references.retain(|&(mast_index, refr_index), reference| {
+                let stale = mast_index == 0 && references.contains(&refr_index);
+                if stale {
+                    info!("Removed stale reference: {} ({refr_index})", reference.id);
+                }
+                !stale
+            });
+        }
+    }
+}
+
+impl std::fmt::Display for ManifestEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}  {}  ({} bytes, hash {:016x})",
+            format_date(self.date),
+            self.plugin,
+            self.size,
+            self.hash
+        )?;
+        write!(
+            f,
+            "\n    added {} records and {} references",
+            self.records.len(),
+            self.references.len()
+        )?;
+        if !self.options.is_empty() {
+            write!(f, "\n    options: {}", self.options.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl PluginData {
+    /// Whether the record is present, excluding those marked as ignored.
+    ///
+    fn contains_record(&self, key: &RecordKey) -> bool {
+        match key {
+            RecordKey::Object(key) => self.objects.get(key).is_some_and(|object| !object.ignored()),
+            RecordKey::Cell(cell_key) => self.cells.get_cell(cell_key).is_some_and(|cell| !cell.ignored()),
+            RecordKey::Landscape(coords) => self
+                .cells
+                .get_exterior(*coords)
+                .and_then(|exterior| exterior.landscape.as_ref())
+                .is_some_and(|landscape| !landscape.ignored()),
+            RecordKey::PathGrid(CellKey::Exterior(coords)) => self
+                .cells
+                .get_exterior(*coords)
+                .and_then(|exterior| exterior.pathgrid.as_ref())
+                .is_some_and(|pathgrid| !pathgrid.ignored()),
+            RecordKey::PathGrid(CellKey::Interior(name)) => self
+                .cells
+                .get_interior(name)
+                .and_then(|interior| interior.pathgrid.as_ref())
+                .is_some_and(|pathgrid| !pathgrid.ignored()),
+            RecordKey::Reference { .. } => false,
+            RecordKey::Dialogue(id) => self.dialogues.get(id).is_some_and(|group| !group.dialogue.ignored()),
+            RecordKey::Info { dialogue, id } => self
+                .dialogues
+                .get(dialogue)
+                .is_some_and(|group| group.infos.iter().any(|info| info.id == *id && !info.ignored())),
+        }
+    }
+
+    /// Remove the record, returning whether it was present.
+    ///
+    /// Note that this can leave behind exteriors and interiors without any records.
+    ///
+    fn remove_record(&mut self, key: &RecordKey) -> bool {
+        match key {
+            RecordKey::Object(key) => self.objects.remove(key).is_some(),
+            RecordKey::Cell(CellKey::Exterior(coords)) => self
+                .cells
+                .get_exterior_mut(*coords)
+                .and_then(|exterior| exterior.cell.take())
+                .is_some(),
+            RecordKey::Cell(CellKey::Interior(name)) => self
+                .cells
+                .get_interior_mut(name)
+                .and_then(|interior| interior.cell.take())
+                .is_some(),
+            RecordKey::Landscape(coords) => self
+                .cells
+                .get_exterior_mut(*coords)
+                .and_then(|exterior| exterior.landscape.take())
+                .is_some(),
+            RecordKey::PathGrid(CellKey::Exterior(coords)) => self
+                .cells
+                .get_exterior_mut(*coords)
+                .and_then(|exterior| exterior.pathgrid.take())
+                .is_some(),
+            RecordKey::PathGrid(CellKey::Interior(name)) => self
+                .cells
+                .get_interior_mut(name)
+                .and_then(|interior| interior.pathgrid.take())
+                .is_some(),
+            RecordKey::Reference { .. } => false,
+            RecordKey::Dialogue(id) => self.dialogues.remove(id).is_some(),
+            RecordKey::Info { dialogue, id } => self
+                .dialogues
+                .get_mut(dialogue)
+                .and_then(|group| group.remove_info(id))
+                .is_some(),
+        }
+    }
+}
+
+/// A hash of the file contents at `path`, used to identify a version of a plugin.
+///
+fn hash_file(path: &Path) -> Result<u64> {
+    let bytes = std::fs::read(path).with_context(|| path.display().to_string())?;
+    Ok(seahash::hash(&bytes))
+}
+
+/// Format seconds since the Unix epoch as a UTC date and time.
+///
+/// (e.g. `"2024-01-31 23:59:59"`)
+///
+pub fn format_date(secs: u64) -> String {
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (hour, minute, second) = (secs / 3600, secs / 60 % 60, secs % 60);
+
+    // Civil from days, see: http://howardhinnant.github.io/date_algorithms.html
+    #[allow(clippy::cast_possible_wrap)]
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_date(0), "1970-01-01 00:00:00");
+        assert_eq!(format_date(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_date(1_706_745_599), "2024-01-31 23:59:59");
+    }
+}
@@ -1,46 +1,255 @@
+use std::ffi::OsStr;
+
+use tes3::esp::{Class, Header, ObjectInfo, Plugin, Race};
+
 use crate::prelude::*;
 
 #[derive(Default)]
 pub struct MergeOptions {
     pub remove_deleted: bool,
-    pub apply_moved_references: bool,
+    /// Pairs of `(old_id, new_id)`, where uses of the old ids are redirected to the new ids.
+    pub replace_ids: Vec<(String, String)>,
+    /// Pairs of `(old_name, new_name)`, where interiors are renamed along with all uses of them.
+    pub rename_interiors: Vec<(String, String)>,
+    /// How records that refer to deleted objects are cleaned, see `remove_deleted`.
+    pub clean: CleanOptions,
+    pub moved_references: MovedReferences,
+    /// Put references into the exterior cell that contains their translation.
+    pub relocate_references: bool,
     pub preserve_duplicate_references: bool,
+    pub duplicate_references: DuplicateReferences,
+    pub report_conflicts: bool,
+    pub later_masters: LaterMasters,
+    pub filter: MergeFilter,
+    /// Keep the contents excluded by `filter` as a plugin that depends on the merged master.
+    pub residual: bool,
+    /// Remove the contents added by a previous version of the plugin, see `MergeManifest`.
+    pub remerge: bool,
+    /// Note the merged plugin in the description of the master's header.
+    pub annotate_header: bool,
+    /// Unify landscape textures that use the same texture file.
+    pub dedupe_textures: bool,
+    /// Remove landscape textures, enchantments and body parts that nothing refers to, and that
+    /// are new to the master. Along with `remove_deleted`, also removes the sound gens of deleted
+    /// creatures.
+    pub remove_unused: bool,
+    /// Move the plugin's exteriors by `(dx, dy)` cells before merging, see `offset_exteriors`.
+    ///
+    /// This is done before filtering, so filters refer to the offset coordinates.
+    pub grid_offset: (i32, i32),
+}
+
+impl MergeOptions {
+    /// The names of all options that differ from their defaults.
+    ///
+    pub fn summary(&self) -> Vec<String> {
+        let flags = [
+            ("remove_deleted", self.remove_deleted),
+            ("clean", self.clean != CleanOptions::DEFAULT),
+            ("replace_ids", !self.replace_ids.is_empty()),
+            ("rename_interiors", !self.rename_interiors.is_empty()),
+            (
+                "apply_moved_references",
+                self.moved_references == MovedReferences::Apply,
+            ),
+            ("drop_moved_references", self.moved_references == MovedReferences::Drop),
+            ("relocate_references", self.relocate_references),
+            ("preserve_duplicate_references", self.preserve_duplicate_references),
+            (
+                "duplicate_references",
+                self.duplicate_references != DuplicateReferences::DEFAULT,
+            ),
+            ("report_conflicts", self.report_conflicts),
+            ("carry_later_masters", self.later_masters == LaterMasters::Carry),
+            ("filter", !self.filter.is_empty()),
+            ("residual", self.residual),
+            ("remerge", self.remerge),
+            ("annotate_header", self.annotate_header),
+            ("dedupe_textures", self.dedupe_textures),
+            ("remove_unused", self.remove_unused),
+            ("grid_offset", self.grid_offset != (0, 0)),
+        ];
+        flags
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then(|| name.to_owned()))
+            .collect()
+    }
+}
+
+pub struct MergeOutput {
+    pub master: PluginData,
+    pub report: MergeReport,
+    /// The contents excluded from the merge, see `MergeOptions::residual`.
+    pub residual: Option<PluginData>,
+    /// The entry to be added to the master's `MergeManifest`.
+    pub manifest_entry: ManifestEntry,
 }
 
 /// Merge the given plugin into the master plugin.
 ///
 #[allow(clippy::ptr_arg)]
 pub fn merge_plugins(plugin_path: &PathBuf, master_path: &PathBuf, options: MergeOptions) -> Result<PluginData> {
+    merge_plugins_with_report(plugin_path, master_path, options).map(|output| output.master)
+}
+
+/// Merge the given plugin into the master plugin, reporting any details worth reviewing.
+///
+#[allow(clippy::ptr_arg)]
+pub fn merge_plugins_with_report(
+    plugin_path: &PathBuf,
+    master_path: &PathBuf,
+    options: MergeOptions,
+) -> Result<MergeOutput> {
+    let mut report = MergeReport::default();
+
+    if options.residual && options.filter.is_empty() {
+        bail!("A residual plugin requires a filter, otherwise nothing is excluded from the merge.");
+    }
+
+    let Some(plugin_name) = plugin_path.file_name().and_then(OsStr::to_str) else {
+        bail!("Invalid plugin path.");
+    };
+
     let mut plugin = PluginData::from_path(plugin_path)?;
     let master_name = plugin.header.ensure_master_present(master_path)?;
 
+    let manifest = MergeManifest::from_master_path(master_path)?;
+    let previous = manifest.latest(plugin_name);
+
+    plugin.offset_exteriors(options.grid_offset)?;
+
+    // Records that are excluded by the filter are still part of the plugin, so are not stale.
+    let current_keys = options.remerge.then(|| plugin.record_keys(plugin_name));
+
+    let mut excluded = None;
+
+    if !options.filter.is_empty() {
+        let contents = options.filter.split(&mut plugin);
+        report.excluded = contents
+            .record_keys(plugin_name)
+            .into_iter()
+            .filter(|key| match key {
+                RecordKey::Reference { .. } => false,
+                // Textures that are copied for the excluded landscapes are still merged.
+                RecordKey::Object(key) => !plugin.objects.contains_key(key),
+                _ => true,
+            })
+            .sorted_unstable()
+            .collect();
+        excluded = Some(contents);
+    }
+
+    if options.report_conflicts {
+        report.conflicts = find_master_conflicts(&plugin, plugin_name, master_path, master_name)?;
+    }
+
     let mut master = merge_masters(&plugin, master_path, master_name)?;
 
-    plugin.remap_masters(&master, master_name);
-    plugin.remap_textures(&master);
-    plugin.merge_into(&mut master);
+    if options.remerge {
+        if let Some(previous) = previous {
+            info!("Removing stale contents of previous merge: {}", previous.plugin);
+            previous.remove_stale(&mut master, current_keys.as_deref().unwrap_or_default());
+        } else {
+            warn!("No previous merge of {plugin_name} was found in the manifest.");
+        }
+    }
 
-    if options.remove_deleted {
-        master.remove_deleted();
+    report.later_master_dependencies = resolve_later_masters(
+        &mut plugin,
+        plugin_name,
+        &master,
+        master_path,
+        master_name,
+        options.later_masters,
+    )?;
+
+    // The indices of references removed by previous merges are not reused.
+    plugin.remap_masters_from(&master, master_name, manifest.next_reference_index());
+    plugin.remap_textures(&mut master)?;
+
+    report.moved_references = resolve_moved_references(&mut plugin, &mut master, master_name, options.moved_references);
+
+    if options.relocate_references {
+        report.relocations = relocate_references(&mut plugin, &mut master, master_name);
     }
 
-    if options.apply_moved_references {
-        master.cells.apply_moved_references();
+    let manifest_entry = ManifestEntry::new(&plugin, plugin_name, plugin_path, &master, &options, previous)?;
+
+    plugin.merge_into(&mut master);
+
+    report.replaced_ids = master.replace_ids(&options.replace_ids)?;
+    report.renamed_interiors = master.rename_interiors(&options.rename_interiors)?;
+
+    if options.remove_deleted {
+        let clean = CleanOptions {
+            remove_sound_gens: options.remove_unused,
+            ..options.clean.clone()
+        };
+        ensure_fallbacks_exist(&master, master_path, &clean)?;
+        report.cleaned = master.remove_deleted_with(&clean);
     }
 
     if !options.preserve_duplicate_references {
-        master.cells.remove_duplicate_references();
+        master.cells.remove_duplicate_references(&options.duplicate_references);
     }
 
+    report.border_duplicates = master.remove_border_duplicates(&options.duplicate_references, master_name);
+
     master.remove_ignored();
 
-    Ok(master)
+    // Records that override other masters must be kept, as they may be in use by those masters.
+    let overridden = if options.dedupe_textures || options.remove_unused {
+        master_object_keys(&master, master_path)?
+    } else {
+        HashSet::new()
+    };
+
+    if options.dedupe_textures {
+        report.duplicate_textures = master.dedupe_textures(&overridden);
+    }
+
+    if options.remove_unused {
+        report.unused = master.remove_unused(master_name, &overridden);
+    }
+
+    report.texture_indices = master.texture_indices();
+
+    if options.annotate_header {
+        annotate_header(&mut master.header, &manifest_entry.annotation());
+    }
+
+    let residual = excluded
+        .filter(|_| options.residual)
+        .map(|excluded| into_residual(excluded, &master, master_name));
+
+    Ok(MergeOutput {
+        master,
+        report,
+        residual,
+        manifest_entry,
+    })
+}
+
+/// Convert the contents excluded from a merge into a plugin that depends on the merged master.
+///
+/// The size of the merged master in the masters list is unknown until it has been saved, so \
+/// it should be updated afterwards with `ensure_master_size`.
+///
+fn into_residual(mut excluded: PluginData, master: &PluginData, master_name: &str) -> PluginData {
+    let mut masters = master.header.masters.clone();
+    masters.push((master_name.into(), 0));
+
+    excluded.remap_masters_to(&masters);
+
+    excluded
 }
 
 /// Create a merged master from the given plugin's masters list.
 ///
 /// Only `master_name` will be loaded in its entirety, others load only types needed for merge logic.
 ///
+/// Masters that follow `master_name` are not included, the merged result must not depend on them.
+///
 fn merge_masters(plugin: &PluginData, master_path: &Path, master_name: &str) -> Result<PluginData> {
     let _guard = set_log_level(Level::WARN);
 
@@ -63,9 +272,109 @@ fn merge_masters(plugin: &PluginData, master_path: &Path, master_name: &str) ->
         }
 
         master.merge_into(&mut merged);
+
+        if name.eq_ignore_ascii_case(master_name) {
+            break;
+        }
     }
 
     merged.header = header;
 
     Ok(merged)
 }
+
+/// Ensure that the fallback race and class are defined, by the master or one of its own masters.
+///
+/// Those masters are expected in the same directory as `master_path`.
+///
+fn ensure_fallbacks_exist(master: &PluginData, master_path: &Path, options: &CleanOptions) -> Result<()> {
+    let fallbacks = [
+        ("race", Race::TAG, &options.fallback_race),
+        ("class", Class::TAG, &options.fallback_class),
+    ];
+
+    let mut missing = Vec::new();
+
+    for (kind, tag, fallback) in fallbacks {
+        let Some(id) = fallback else {
+            continue;
+        };
+        match master.objects.get(&(tag, id.to_ascii_lowercase())) {
+            Some(object) if object.deleted() => bail!("Fallback {kind} '{id}' is deleted."),
+            Some(_) => {}
+            None => missing.push((kind, tag, id)),
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let _guard = set_log_level(Level::WARN);
+
+    let mut path = master_path.to_owned();
+
+    for (name, _) in &master.header.masters {
+        path.set_file_name(name);
+
+        let plugin = Plugin::from_path_filtered(&path, |tag| matches!(&tag, Race::TAG | Class::TAG))
+            .with_context(|| path.display().to_string())?;
+        let objects = PluginData::from_plugin(plugin).objects;
+
+        missing.retain(|&(_, tag, id)| !objects.contains_key(&(tag, id.to_ascii_lowercase())));
+    }
+
+    if let Some((kind, _, id)) = missing.first() {
+        bail!("Fallback {kind} '{id}' was not found in the master or its masters.");
+    }
+
+    Ok(())
+}
+
+/// Append a line to the description of the header, if there is space remaining.
+///
+fn annotate_header(header: &mut Header, annotation: &str) {
+    const MAX_LEN: usize = 255; // Leave space for the null terminator.
+
+    let separator = if header.description.is_empty() { "" } else { "\r\n" };
+
+    if header.description.len() + separator.len() + annotation.len() > MAX_LEN {
+        warn!("Header description is too long to add: {annotation}");
+        return;
+    }
+
+    header.description.push_str(separator);
+    header.description.push_str(annotation);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn annotate_header_limit() {
+        let annotation = "Merged Plugin.esp (2024-01-31)";
+
+        let mut header = Header::default();
+        annotate_header(&mut header, annotation);
+        assert_eq!(header.description, annotation);
+
+        // Exactly fills the description, including the separator.
+        let mut header = Header {
+            description: "a".repeat(255 - 2 - annotation.len()),
+            ..default()
+        };
+        annotate_header(&mut header, annotation);
+        assert_eq!(header.description.len(), 255);
+        assert!(header.description.ends_with(&format!("\r\n{annotation}")));
+
+        // One character too many, the description is left unchanged.
+        let description = "a".repeat(255 - 1 - annotation.len());
+        let mut header = Header {
+            description: description.clone(),
+            ..default()
+        };
+        annotate_header(&mut header, annotation);
+        assert_eq!(header.description, description);
+    }
+}
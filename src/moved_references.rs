@@ -0,0 +1,268 @@
+use serde::Serialize;
+use tes3::esp::ObjectInfo;
+
+use crate::prelude::*;
+
+/// How to handle the plugin's 'moved references'.
+///
+/// These are references that remain in the reference list of their original exterior cell,
+/// but specify a different cell that they now belong to.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum MovedReferences {
+    /// Leave moved references as they are.
+    #[default]
+    Keep,
+    /// Put moved references into the reference list of the cell that contains them.
+    ///
+    /// References owned by other masters cannot leave their original cell, so they remain
+    /// moved references, but their destination is corrected to match their translation.
+    Apply,
+    /// Remove moved references from the plugin, leaving any master's version of them as is.
+    Drop,
+}
+
+/// What was done to a moved reference.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum MoveResult {
+    Kept,
+    Applied,
+    Dropped,
+    /// The cell that contains the reference is not defined by the plugin or the merge target,
+    /// so it remains a moved reference in its original cell.
+    Unresolved,
+}
+
+/// A moved reference of the plugin.
+///
+#[derive(Serialize)]
+pub struct MovedReference {
+    /// The reference, in its original cell.
+    pub key: RecordKey,
+    pub id: String,
+    /// The cell the reference specified that it was moved to.
+    pub destination: (i32, i32),
+    /// The cell that actually contains the reference's translation.
+    pub expected: (i32, i32),
+    pub result: MoveResult,
+}
+
+/// A reference of the plugin that was outside the bounds of its exterior cell.
+///
+#[derive(Serialize)]
+pub struct Relocation {
+    /// The reference, in its original cell.
+    pub key: RecordKey,
+    pub id: String,
+    pub from: (i32, i32),
+    pub to: (i32, i32),
+    /// The reference was made a moved reference instead, as it is owned by another master or
+    /// its new cell is not defined by the plugin or the merge target.
+    pub moved_reference: bool,
+}
+
+impl MovedReference {
+    /// Whether the specified destination agrees with the reference's translation.
+    ///
+    pub fn is_valid(&self) -> bool {
+        self.destination == self.expected
+    }
+}
+
+impl std::str::FromStr for MovedReferences {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::Keep),
+            "apply" => Ok(Self::Apply),
+            "drop" => Ok(Self::Drop),
+            _ => bail!("Invalid moved references mode, expected keep, apply or drop: {s}"),
+        }
+    }
+}
+
+/// Handle the moved references of the plugin as specified by `policy`.
+///
+/// This must be done after the plugin's masters were remapped, but before it is merged, so that
+/// references owned by the merge target can still be told apart from the master's versions.
+///
+pub fn resolve_moved_references(
+    plugin: &mut PluginData,
+    master: &mut PluginData,
+    master_name: &str,
+    policy: MovedReferences,
+) -> Vec<MovedReference> {
+    let moved_references = plugin
+        .cells
+        .exteriors
+        .iter_mut()
+        .filter_map(|(&coords, exterior)| Some((coords, exterior.cell.as_mut()?)))
+        .flat_map(|(coords, cell)| {
+            cell.references
+                .extract_if(|_, reference| reference.moved_cell.is_some())
+                .map(move |(key, reference)| (coords, key, reference))
+        })
+        .collect_vec();
+
+    let mut results = Vec::with_capacity(moved_references.len());
+
+    for (source, key, mut reference) in moved_references {
+        let destination = reference.moved_cell.unwrap();
+        let expected = grid_coords(reference.translation);
+
+        // The cell whose reference list will contain the reference, if any.
+        let mut target = Some(source);
+
+        let result = match policy {
+            MovedReferences::Keep => MoveResult::Kept,
+            MovedReferences::Drop => {
+                target = None;
+                MoveResult::Dropped
+            }
+            MovedReferences::Apply if expected == source => {
+                reference.moved_cell = None;
+                MoveResult::Applied
+            }
+            MovedReferences::Apply if key.0 != 0 => {
+                reference.moved_cell = Some(expected);
+                MoveResult::Kept
+            }
+            MovedReferences::Apply if !ensure_exterior_cell(plugin, master, expected) => {
+                warn!(
+                    "Moved reference '{}' ({}) has no destination cell {:?}",
+                    reference.id, key.1, expected
+                );
+                reference.moved_cell = Some(expected);
+                MoveResult::Unresolved
+            }
+            MovedReferences::Apply => {
+                remove_master_version(master, source, key);
+                reference.moved_cell = None;
+                target = Some(expected);
+                MoveResult::Applied
+            }
+        };
+
+        results.push(MovedReference {
+            key: reference_key(plugin, master_name, source, key),
+            id: reference.id.clone(),
+            destination,
+            expected,
+            result,
+        });
+
+        if let Some(coords) = target
+            && let Some(cell) = plugin.cells.get_exterior_mut(coords).and_then(|e| e.cell.as_mut())
+        {
+            cell.references.insert(key, reference);
+        }
+    }
+
+    results
+}
+
+/// Put references of the plugin's exteriors into the cell that contains their translation.
+///
+/// References owned by the plugin or the merge target are moved into that cell's reference list. \
+/// References owned by other masters cannot leave their original cell, so become moved references.
+/// The same applies to references whose new cell is not defined by the plugin or the merge target.
+///
+/// Like `resolve_moved_references`, this must be done after remapping masters but before merging.
+///
+pub fn relocate_references(plugin: &mut PluginData, master: &mut PluginData, master_name: &str) -> Vec<Relocation> {
+    let misplaced = plugin
+        .cells
+        .exteriors
+        .iter_mut()
+        .filter_map(|(&coords, exterior)| Some((coords, exterior.cell.as_mut()?)))
+        .flat_map(|(coords, cell)| {
+            cell.references
+                .extract_if(move |_, reference| {
+                    !reference.deleted()
+                        && reference.moved_cell.is_none()
+                        && grid_coords(reference.translation) != coords
+                })
+                .map(move |(key, reference)| (coords, key, reference))
+        })
+        .collect_vec();
+
+    let mut relocations = Vec::with_capacity(misplaced.len());
+
+    for (source, key, mut reference) in misplaced {
+        let target = grid_coords(reference.translation);
+        let is_moved = key.0 == 0 && ensure_exterior_cell(plugin, master, target);
+
+        if is_moved {
+            info!(
+                "Relocating reference '{}' ({}) from cell {:?} to {:?}",
+                reference.id, key.1, source, target
+            );
+        } else {
+            info!(
+                "Relocating reference '{}' ({}) from cell {:?} to {:?} as a moved reference",
+                reference.id, key.1, source, target
+            );
+        }
+
+        relocations.push(Relocation {
+            key: reference_key(plugin, master_name, source, key),
+            id: reference.id.clone(),
+            from: source,
+            to: target,
+            moved_reference: !is_moved,
+        });
+
+        let cell = if is_moved {
+            remove_master_version(master, source, key);
+            target
+        } else {
+            reference.moved_cell = Some(target);
+            source
+        };
+
+        if let Some(cell) = plugin.cells.get_exterior_mut(cell).and_then(|e| e.cell.as_mut()) {
+            cell.references.insert(key, reference);
+        }
+    }
+
+    relocations
+}
+
+/// Ensure the plugin has a cell at `coords`, copying the master's cell into the plugin if needed.
+///
+/// Cells that are ignored do not belong to the merge target, so they cannot be used. Those are
+/// loaded without most of their data, which an override would otherwise erase.
+///
+fn ensure_exterior_cell(plugin: &mut PluginData, master: &PluginData, coords: (i32, i32)) -> bool {
+    if plugin.cells.get_exterior(coords).is_some_and(|e| e.cell.is_some()) {
+        return true;
+    }
+    let Some(cell) = master.cells.get_exterior(coords).and_then(|e| e.cell.as_ref()) else {
+        return false;
+    };
+    if cell.ignored() {
+        return false;
+    }
+    let mut copy = cell.clone();
+    copy.references.clear();
+    plugin.cells.get_or_create_exterior(coords).cell = Some(copy);
+    true
+}
+
+/// Remove the merge target's version of a reference that is leaving its original cell.
+///
+fn remove_master_version(master: &mut PluginData, coords: (i32, i32), key: (u32, u32)) {
+    if let Some(cell) = master.cells.get_exterior_mut(coords).and_then(|e| e.cell.as_mut()) {
+        cell.references.remove(&key);
+    }
+}
+
+fn reference_key(plugin: &PluginData, master_name: &str, coords: (i32, i32), key: (u32, u32)) -> RecordKey {
+    RecordKey::Reference {
+        cell: CellKey::Exterior(coords),
+        owner: plugin.header.owner_name(key.0, master_name),
+        index: key.1,
+    }
+}
@@ -0,0 +1,98 @@
+use tes3::esp::{Bodypart, BodypartType, Enchanting, LandscapeTexture, ObjectInfo, Plugin, TES3Object};
+
+use crate::prelude::*;
+
+impl PluginData {
+    /// Remove records that nothing in this plugin refers to.
+    ///
+    /// These are landscape textures that no landscape uses, enchantments that no item uses, and
+    /// armor or clothing body parts that no item uses. Records whose ids appear anywhere in the
+    /// text of a script or `INFO` are kept, as scripts may use them by name.
+    ///
+    /// The remaining landscape textures are compacted afterwards, see `compact_textures`.
+    ///
+    /// Only records that are new to this plugin are removed. The keys of the records that its
+    /// masters define are given by `overridden`, see `master_object_keys`.
+    ///
+    /// The `file_name` is the name of this plugin, used to resolve local references.
+    ///
+    /// Returns the keys of the removed records.
+    ///
+    pub fn remove_unused(&mut self, file_name: &str, overridden: &HashSet<TaggedId>) -> Vec<RecordKey> {
+        let usage_index = self.usage_index(file_name);
+        let script_text = self.script_text();
+        let used_textures = self.used_texture_indices();
+
+        let removed = self
+            .objects
+            .extract_if(|key, object| {
+                if object.ignored() || overridden.contains(key) {
+                    return false;
+                }
+                let id = &key.1;
+                let is_unused = match object {
+                    TES3Object::LandscapeTexture(texture) => {
+                        landscape_index(texture).is_none_or(|index| !used_textures.contains(&index))
+                    }
+                    TES3Object::Enchanting(_) => usage_index.get(id).is_empty(),
+                    TES3Object::Bodypart(bodypart) => {
+                        matches!(bodypart.data.bodypart_type, BodypartType::Armor | BodypartType::Clothing)
+                            && usage_index.get(id).is_empty()
+                    }
+                    _ => false,
+                };
+                is_unused && !script_text.contains(&id.to_ascii_lowercase())
+            })
+            .map(|(key, _)| RecordKey::Object(key))
+            .sorted_unstable()
+            .collect_vec();
+
+        for key in &removed {
+            info!("Removed unused record: {key}");
+        }
+
+        self.compact_textures();
+
+        removed
+    }
+
+    /// The lowercase text of every script and `INFO` result, separated by newlines.
+    ///
+    fn script_text(&self) -> String {
+        let scripts = self.objects.values().filter_map(|object| match object {
+            TES3Object::Script(script) => Some(script.text.as_str()),
+            _ => None,
+        });
+        let infos = self
+            .dialogues
+            .values()
+            .flat_map(|group| &group.infos)
+            .map(|info| info.script_text.as_str());
+        scripts.chain(infos).join("\n").to_ascii_lowercase()
+    }
+}
+
+/// Load the keys of the objects defined by the masters of `master`, limited to the types that
+/// `remove_unused` and `dedupe_textures` could remove.
+///
+/// Those masters are expected in the same directory as `master_path`.
+///
+pub fn master_object_keys(master: &PluginData, master_path: &Path) -> Result<HashSet<TaggedId>> {
+    let _guard = set_log_level(Level::WARN);
+
+    let mut keys = HashSet::new();
+    let mut path = master_path.to_owned();
+
+    for (name, _) in &master.header.masters {
+        path.set_file_name(name);
+
+        let plugin = Plugin::from_path_filtered(&path, |tag| {
+            matches!(&tag, LandscapeTexture::TAG | Enchanting::TAG | Bodypart::TAG)
+        })
+        .with_context(|| path.display().to_string())?;
+
+        keys.extend(PluginData::from_plugin(plugin).objects.into_keys());
+    }
+
+    Ok(keys)
+}
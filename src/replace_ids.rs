@@ -0,0 +1,203 @@
+use serde::Serialize;
+use tes3::esp::{ObjectInfo, Reference};
+
+use crate::prelude::*;
+
+/// A field that was redirected from a retired object to its replacement.
+///
+#[derive(Serialize)]
+pub struct ReplacedId {
+    /// Describes the record. (e.g. `NPC_ fargoth`)
+    pub record: String,
+    pub field: String,
+    pub old: String,
+    pub new: String,
+}
+
+impl PluginData {
+    /// Redirect every use of an id to its replacement.
+    ///
+    /// The `replacements` are pairs of `(old_id, new_id)`, where ids are case-insensitive. Only
+    /// fields that refer to other objects are changed, the objects themselves are not renamed.
+    ///
+    /// Fails if a new id is too long for a field that would hold it.
+    ///
+    pub fn replace_ids(&mut self, replacements: &[(String, String)]) -> Result<Vec<ReplacedId>> {
+        self.replace_ids_of_kinds(replacements, IdKinds::all())
+    }
+
+    /// Rename interior cells, and redirect every use of their old names to the new names.
+    ///
+    /// The `renames` are pairs of `(old_name, new_name)`, where names are case-insensitive. This
+    /// updates door and travel destinations, AI packages, dialogue filters and path grids.
+    ///
+    /// Fails if an interior would be renamed to the name of another existing interior, if it is
+    /// not defined by this plugin, or if a new name is too long for a field that would hold it. Interiors of other masters are ignored and discarded
+    /// later, which would leave the redirected fields referring to a cell that does not exist.
+    ///
+    pub fn rename_interiors(&mut self, renames: &[(String, String)]) -> Result<Vec<ReplacedId>> {
+        for (old, new) in renames {
+            if !old.eq_ignore_ascii_case(new) && self.cells.get_interior(new).is_some() {
+                bail!("Cannot rename interior '{old}' to '{new}', an interior of that name already exists.");
+            }
+            let Some(interior) = self.cells.get_interior(old) else {
+                warn!("Interior to rename was not found: {old}");
+                continue;
+            };
+            if interior.cell.as_ref().is_none_or(|cell| cell.ignored()) {
+                bail!("Cannot rename interior '{old}', it is not defined by the merge target or the plugin.");
+            }
+            let mut interior = self.cells.interiors.remove(old.as_uncased()).unwrap();
+            info!("Renaming interior: '{old}' -> '{new}'");
+            if let Some(cell) = &mut interior.cell {
+                cell.name.clone_from(new);
+            }
+            if let Some(pathgrid) = &mut interior.pathgrid {
+                pathgrid.cell.clone_from(new);
+            }
+            self.cells.interiors.insert(new.clone().into(), interior);
+        }
+
+        self.replace_ids_of_kinds(renames, IdKinds::CELL)
+    }
+
+    /// Redirect uses of ids in fields that refer to any of the given `kinds` of objects.
+    ///
+    fn replace_ids_of_kinds(&mut self, replacements: &[(String, String)], kinds: IdKinds) -> Result<Vec<ReplacedId>> {
+        if replacements.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let replacements: HashMap<UString, &str> = replacements
+            .iter()
+            .map(|(old, new)| (old.clone().into(), new.as_str()))
+            .collect();
+
+        let mut replacers: Vec<_> = self
+            .objects
+            .par_values_mut()
+            .map(|object| {
+                let record = format!("{} {}", object.tag_str(), object.editor_id());
+                let mut replacer = Replacer::new(&replacements, kinds, record);
+                object.visit_ids(&mut replacer);
+                replacer
+            })
+            .collect();
+
+        for cell in self.cells.iter_mut() {
+            let mut replacer = Replacer::new(&replacements, kinds, format!("CELL '{}'", cell.editor_id()));
+            cell.visit_ids(&mut replacer);
+            replacers.push(replacer);
+        }
+
+        for (topic, group) in &mut self.dialogues {
+            for info in &mut group.infos {
+                let mut replacer = Replacer::new(&replacements, kinds, format!("INFO {topic} ({})", info.id));
+                info.visit_ids(&mut replacer);
+                replacers.push(replacer);
+            }
+        }
+
+        let too_long = replacers.iter().flat_map(|replacer| &replacer.too_long).join("\n");
+        if !too_long.is_empty() {
+            bail!("Cannot replace ids that are too long for the fields that hold them:\n{too_long}");
+        }
+
+        let replaced = replacers
+            .into_iter()
+            .flat_map(|replacer| replacer.replaced)
+            .collect_vec();
+
+        for replacement in &replaced {
+            info!(
+                "Replaced id: {}: {} '{}' -> '{}'",
+                replacement.record, replacement.field, replacement.old, replacement.new
+            );
+        }
+
+        Ok(replaced)
+    }
+}
+
+/// Parse a replacement in the form `OLD=NEW`.
+///
+pub fn id_replacement(arg: &str) -> Result<(String, String)> {
+    split_pair(arg).with_context(|| format!("Invalid id replacement, expected OLD=NEW: {arg}"))
+}
+
+/// Parse an interior rename in the form `OLD=NEW`.
+///
+pub fn interior_rename(arg: &str) -> Result<(String, String)> {
+    split_pair(arg).with_context(|| format!("Invalid interior rename, expected OLD=NEW: {arg}"))
+}
+
+fn split_pair(arg: &str) -> Option<(String, String)> {
+    match arg.split_once('=') {
+        Some((old, new)) if !old.trim().is_empty() && !new.trim().is_empty() => {
+            Some((old.trim().to_owned(), new.trim().to_owned()))
+        }
+        _ => None,
+    }
+}
+
+struct Replacer<'a> {
+    replacements: &'a HashMap<UString, &'a str>,
+    /// Only fields that refer to these kinds of objects are replaced.
+    kinds: IdKinds,
+    /// Describes the record being visited.
+    base: String,
+    /// Describes the record or reference whose fields are being visited.
+    record: String,
+    replaced: Vec<ReplacedId>,
+    /// Describes the replacements that did not fit in their fields.
+    too_long: Vec<String>,
+}
+
+impl<'a> Replacer<'a> {
+    fn new(replacements: &'a HashMap<UString, &'a str>, kinds: IdKinds, record: String) -> Self {
+        Self {
+            replacements,
+            kinds,
+            base: record.clone(),
+            record,
+            replaced: Vec::new(),
+            too_long: Vec::new(),
+        }
+    }
+}
+
+impl IdVisitor for Replacer<'_> {
+    fn visit(&mut self, field: &str, kinds: IdKinds, id: &mut String) -> bool {
+        if !kinds.intersects(self.kinds) {
+            return true;
+        }
+        if let Some(&new) = self.replacements.get(id.as_uncased()) {
+            self.replaced.push(ReplacedId {
+                record: self.record.clone(),
+                field: field.to_owned(),
+                old: std::mem::replace(id, new.to_owned()),
+                new: new.to_owned(),
+            });
+        }
+        true
+    }
+
+    fn visit_limited(&mut self, field: &str, kinds: IdKinds, id: &mut String, max_len: usize) -> bool {
+        if kinds.intersects(self.kinds)
+            && let Some(&new) = self.replacements.get(id.as_uncased())
+            && new.len() > max_len
+        {
+            self.too_long.push(format!(
+                "{}: {field} '{id}' -> '{new}' (at most {max_len} characters)",
+                self.record
+            ));
+            return true;
+        }
+        self.visit(field, kinds, id)
+    }
+
+    fn visit_reference(&mut self, indices: (u32, u32), reference: &mut Reference) -> bool {
+        self.record = format!("{} REFR '{}' {indices:?}", self.base, reference.id);
+        self.visit("id", IdKinds::PHYSICAL, &mut reference.id)
+    }
+}
@@ -21,3 +21,6 @@ pub use remove_deleted::*;
 
 mod remove_ignored;
 pub use remove_ignored::*;
+
+mod visit_ids;
+pub use visit_ids::*;
@@ -10,30 +10,44 @@ impl Header {
     ///
     /// If the name was not present it will be inserted at the end of the list.
     ///
+    /// Note the master is not required to be last in the list, see `resolve_later_masters`.
+    ///
     pub fn ensure_master_present<'a>(&mut self, master_path: &'a Path) -> Result<&'a str> {
         let Some(master_name) = master_path.file_name().and_then(OsStr::to_str) else {
             bail!("Invalid master path.");
         };
 
-        let master_position = self
+        let master_present = self
             .masters
             .iter()
-            .position(|(name, _)| name.eq_ignore_ascii_case(master_name));
+            .any(|(name, _)| name.eq_ignore_ascii_case(master_name));
 
-        match master_position {
-            Some(i) if i != (self.masters.len() - 1) => {
-                bail!("Merge target must be the last master in plugin's master list.");
-            }
-            None => {
-                self.masters.push((master_name.into(), master_path.metadata()?.len()));
-            }
-            _ => {
-                // The master is present and is the last in the list, nothing to do.
-            }
+        if !master_present {
+            self.masters.push((master_name.into(), master_path.metadata()?.len()));
         }
 
         Ok(master_name)
     }
+
+    /// Update the size of the master at `master_path` to match its size on disk.
+    ///
+    pub fn ensure_master_size(&mut self, master_path: &Path) -> Result<()> {
+        let Some(master_name) = master_path.file_name().and_then(OsStr::to_str) else {
+            bail!("Invalid master path.");
+        };
+
+        let Some((_, size)) = self
+            .masters
+            .iter_mut()
+            .find(|(name, _)| name.eq_ignore_ascii_case(master_name))
+        else {
+            bail!("Master is missing from the masters list: {master_name}");
+        };
+
+        *size = master_path.metadata()?.len();
+
+        Ok(())
+    }
 }
 
 #[ext]
@@ -1,5 +1,8 @@
 use crate::prelude::*;
 
+/// Maps the old reference indices of a master to the new indices they were given when flattened.
+pub type RefrIndexRemap = HashMap<u32, u32>;
+
 pub trait RemapMasters {
     /// Remap the references of `plugin` to be compatible with `master`.
     ///
@@ -77,33 +80,133 @@ pub trait RemapMasters {
     /// this function does.
     ///
     fn remap_masters(&mut self, master: &PluginData, master_name: &str);
+
+    /// Remap the references of `plugin` to be compatible with `master`.
+    ///
+    /// Like `remap_masters` except that local references are numbered from at least `min_index`.
+    ///
+    fn remap_masters_from(&mut self, master: &PluginData, master_name: &str, min_index: u32);
+
+    /// Remap the references of `plugin` to be compatible with `master`.
+    ///
+    /// Like `remap_masters` except that `master` is the result of flattening all of the \
+    /// given `flattened` masters into a single file. References of all those masters will be \
+    /// remapped to be local, using the reference indices they were given when flattened.
+    ///
+    /// Returns the new reference indices of the local references of `plugin`.
+    ///
+    fn remap_masters_flattened(
+        &mut self,
+        master: &PluginData,
+        flattened: &[(String, RefrIndexRemap)],
+    ) -> RefrIndexRemap;
+
+    /// Remap the references of `plugin` to be compatible with the given masters list.
+    ///
+    /// Any masters from `plugin` that were not already present are added to the end of \
+    /// the list. Unlike `remap_masters` the local references are left unmodified.
+    ///
+    fn remap_masters_to(&mut self, masters: &[(String, u64)]);
 }
 
 impl RemapMasters for PluginData {
     fn remap_masters(&mut self, master: &PluginData, master_name: &str) {
-        let (new_masters, index_remap) = get_index_remap(&self.header.masters, &master.header.masters, master_name);
+        self.remap_masters_from(master, master_name, 1);
+    }
+
+    fn remap_masters_from(&mut self, master: &PluginData, master_name: &str, min_index: u32) {
+        remap_masters_with(self, master, min_index, |name| name.eq_ignore_ascii_case(master_name));
+    }
+
+    fn remap_masters_flattened(
+        &mut self,
+        master: &PluginData,
+        flattened: &[(String, RefrIndexRemap)],
+    ) -> RefrIndexRemap {
+        let find_flattened = |name: &str| {
+            flattened
+                .iter()
+                .find(|(master_name, _)| name.eq_ignore_ascii_case(master_name))
+                .map(|(_, refr_remap)| refr_remap)
+        };
+
+        // The references of earlier masters were renumbered when they were flattened.
+        let refr_remaps: Vec<_> = std::iter::once(None)
+            .chain(self.header.masters.iter().map(|(name, _)| find_flattened(name)))
+            .collect();
+
+        let identity = (0..=self.header.masters.len() as u32).collect();
+        let (new_masters, index_remap) = get_index_remap_with(&self.header.masters, &master.header.masters, |name| {
+            find_flattened(name).is_some()
+        });
 
-        // Copy author/description/etc from the master file to the plugin file.
         self.header = master.header.clone();
 
         if let Some(masters) = new_masters {
             self.header.masters = masters;
         }
 
+        // Local references must always be renumbered to avoid those of the flattened masters.
+        let indices = index_remap.unwrap_or(identity);
+        let start_index = next_reference_index(master);
+        apply_index_remap(self, &indices, &refr_remaps, start_index)
+    }
+
+    fn remap_masters_to(&mut self, masters: &[(String, u64)]) {
+        let masters = masters.to_vec();
+        let (new_masters, index_remap) = get_index_remap_with(&self.header.masters, &masters, |_| false);
+
+        self.header.masters = new_masters.unwrap_or(masters);
+
         if let Some(indices) = index_remap {
-            let start_index = next_reference_index(master);
-            apply_index_remap(self, &indices, start_index);
+            for cell in self.cells.iter_mut() {
+                cell.references = std::mem::take(&mut cell.references)
+                    .into_iter()
+                    .map(|((mast_index, refr_index), mut reference)| {
+                        let mast_index = indices[mast_index as usize];
+                        reference.mast_index = mast_index;
+                        ((mast_index, refr_index), reference)
+                    })
+                    .collect();
+            }
         }
     }
 }
 
+fn remap_masters_with(plugin: &mut PluginData, master: &PluginData, min_index: u32, is_target: impl Fn(&str) -> bool) {
+    let (new_masters, index_remap) = get_index_remap_with(&plugin.header.masters, &master.header.masters, is_target);
+
+    // Copy author/description/etc from the master file to the plugin file.
+    plugin.header = master.header.clone();
+
+    if let Some(masters) = new_masters {
+        plugin.header.masters = masters;
+    }
+
+    if let Some(indices) = index_remap {
+        let start_index = next_reference_index(master).max(min_index);
+        apply_index_remap(plugin, &indices, &[], start_index);
+    }
+}
+
 type Masters = Vec<(String, u64)>; // (name, size)
 type Indices = Vec<u32>;
 
+#[cfg(test)]
 fn get_index_remap(
     plugin_masters: &Masters,
     master_masters: &Masters,
     target_master: &str,
+) -> (Option<Masters>, Option<Indices>) {
+    get_index_remap_with(plugin_masters, master_masters, |name| {
+        name.eq_ignore_ascii_case(target_master)
+    })
+}
+
+fn get_index_remap_with(
+    plugin_masters: &Masters,
+    master_masters: &Masters,
+    is_target: impl Fn(&str) -> bool,
 ) -> (Option<Masters>, Option<Indices>) {
     let mut new_masters = Vec::with_capacity(10);
     let mut index_remap = Vec::with_capacity(10);
@@ -113,8 +216,8 @@ fn get_index_remap(
 
     for master in plugin_masters {
         index_remap.push({
-            // If it matches target_master then remap references to local.
-            if master.0.eq_ignore_ascii_case(target_master) {
+            // If it matches a target master then remap references to local.
+            if is_target(&master.0) {
                 0
             }
             // Otherwise remap it to the master position in `new_masters`.
@@ -159,17 +262,27 @@ fn next_reference_index(plugin: &PluginData) -> u32 {
         .map_or(1, |i| i + 1)
 }
 
-fn apply_index_remap(plugin: &mut PluginData, index_remap: &[u32], start_index: u32) {
+fn apply_index_remap(
+    plugin: &mut PluginData,
+    index_remap: &[u32],
+    refr_remaps: &[Option<&RefrIndexRemap>],
+    start_index: u32,
+) -> RefrIndexRemap {
     let mut next_index = start_index;
+    let mut local_remap = RefrIndexRemap::new();
 
     for cell in plugin.cells.iter_mut() {
         cell.references = std::mem::take(&mut cell.references)
             .into_iter()
             .map(|((mut mast_index, mut refr_index), mut reference)| {
                 if mast_index == 0 {
+                    local_remap.insert(refr_index, next_index);
                     refr_index = next_index;
                     next_index += 1;
                 } else {
+                    if let Some(Some(refr_remap)) = refr_remaps.get(mast_index as usize) {
+                        refr_index = refr_remap.get(&refr_index).copied().unwrap_or(refr_index);
+                    }
                     mast_index = index_remap[mast_index as usize];
                 }
                 reference.mast_index = mast_index;
@@ -178,6 +291,8 @@ fn apply_index_remap(plugin: &mut PluginData, index_remap: &[u32], start_index:
             })
             .collect();
     }
+
+    local_remap
 }
 
 #[cfg(test)]
@@ -274,6 +389,23 @@ mod test {
         assert_eq!(indices, Some(vec![0, 1, 0, 3]));
     }
 
+    #[test]
+    fn plugin_merging_into_flattened_masters() {
+        let plugin_masters = masters_vec(&["A", "B", "C"]);
+        let master_masters = masters_vec(&[]);
+        let (masters, indices) =
+            get_index_remap_with(&plugin_masters, &master_masters, |name| matches!(name, "A" | "B"));
+
+        // Flattened masters are omitted from the master list.
+        assert_eq!(masters, Some(masters_vec(&["C"])));
+
+        // 0 = reserved
+        // 0 = "A" was flattened so it is moved to position 0
+        // 0 = "B" was flattened so it is moved to position 0
+        // 1 = "C" has moved, it's now at position 1 in the new list
+        assert_eq!(indices, Some(vec![0, 0, 0, 1]));
+    }
+
     #[test]
     fn mismatched_masters_of_consistent_order() {
         let plugin_masters = masters_vec(&["A", "B"]);
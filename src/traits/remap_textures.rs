@@ -1,6 +1,8 @@
+use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
 
-use tes3::esp::{LandscapeTexture, TES3Object};
+use serde::Serialize;
+use tes3::esp::{LandscapeTexture, ObjectInfo, TES3Object};
 
 use crate::prelude::*;
 
@@ -11,17 +13,208 @@ pub trait RemapTextures {
     /// This is necessary as texture indices inside plugins are "local" to the file
     /// and will differ between plugins even if they actualy mean the same texture.
     ///
-    fn remap_textures(&mut self, master: &PluginData);
+    /// If the combined textures would not fit into the indices that landscapes can refer to,
+    /// the master's textures are compacted first, see `compact_textures`.
+    ///
+    fn remap_textures(&mut self, master: &mut PluginData) -> Result<(), TextureError>;
 }
 
 impl RemapTextures for PluginData {
-    fn remap_textures(&mut self, master: &PluginData) {
+    fn remap_textures(&mut self, master: &mut PluginData) -> Result<(), TextureError> {
+        if required_indices(self, master) > TEXTURE_INDEX_LIMIT {
+            info!("Compacting texture indices of the master...");
+            master.compact_textures();
+        }
+
+        let required = required_indices(self, master);
+        if required > TEXTURE_INDEX_LIMIT {
+            return Err(TextureError::TooManyTextures { required });
+        }
+
         if let Some(index_remap) = get_index_remap(self, master) {
             apply_index_remap(self, &index_remap);
         }
+
+        Ok(())
+    }
+}
+
+/// The number of texture indices that landscapes can refer to.
+///
+/// Landscapes store indices as `u16` values plus one, where 0 is reserved for "no texture".
+///
+pub const TEXTURE_INDEX_LIMIT: u32 = 0xFFFF;
+
+#[derive(Debug)]
+pub enum TextureError {
+    /// The landscape textures require more indices than landscapes can refer to.
+    TooManyTextures { required: u32 },
+}
+
+impl fmt::Display for TextureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyTextures { required } => write!(
+                f,
+                "Too many landscape textures: {required} indices are required, but the limit is {TEXTURE_INDEX_LIMIT}."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+/// A landscape texture that was unified with another texture using the same file.
+///
+#[derive(Serialize)]
+pub struct DuplicateTexture {
+    pub id: String,
+    /// The texture that replaced this one in landscapes.
+    pub original: String,
+    pub file_name: String,
+}
+
+impl PluginData {
+    /// Unify landscape textures that use the same texture file, ignoring case and slashes.
+    ///
+    /// Landscapes are updated to use the texture that overrides a master if there is one, or else
+    /// the texture with the lowest index. Textures that override a master are never removed, see
+    /// `master_object_keys`. The others are removed, and the remaining textures are compacted
+    /// afterwards, see `compact_textures`.
+    ///
+    pub fn dedupe_textures(&mut self, overridden: &HashSet<TaggedId>) -> Vec<DuplicateTexture> {
+        let mut originals: HashMap<String, (u32, String)> = HashMap::new();
+
+        let textures = self
+            .objects
+            .iter()
+            .filter_map(|(key, object)| match object {
+                TES3Object::LandscapeTexture(texture) if !texture.ignored() => Some((key, texture)),
+                _ => None,
+            })
+            .sorted_by_key(|(key, texture)| (!overridden.contains(*key), texture.index))
+            .collect_vec();
+
+        let mut index_remap = IndexRemap::new();
+        let mut duplicates = Vec::new();
+        let mut removed = HashSet::new();
+
+        for (key, texture) in textures {
+            let file_name = texture.file_name.to_ascii_lowercase().replace('/', "\\");
+            match originals.entry(file_name) {
+                Entry::Vacant(entry) => {
+                    entry.insert((texture.index, texture.id.clone()));
+                }
+                Entry::Occupied(_) if overridden.contains(key) => {}
+                Entry::Occupied(entry) => {
+                    let (index, original) = entry.get();
+                    info!("Unifying duplicate texture: {} -> {original} ({})", texture.id, texture.file_name);
+                    if let (Ok(old_index), Ok(new_index)) = (u16::try_from(texture.index), u16::try_from(*index)) {
+                        index_remap.insert(old_index + 1, new_index + 1);
+                    }
+                    removed.insert(key.clone());
+                    duplicates.push(DuplicateTexture {
+                        id: texture.id.clone(),
+                        original: original.clone(),
+                        file_name: texture.file_name.clone(),
+                    });
+                }
+            }
+        }
+
+        if duplicates.is_empty() {
+            return duplicates;
+        }
+
+        apply_index_remap(self, &index_remap);
+
+        self.objects.retain(|key, _| !removed.contains(key));
+
+        self.compact_textures();
+
+        duplicates
+    }
+
+    /// Renumber the landscape textures so that their indices have no gaps between them, and
+    /// update the landscapes to match.
+    ///
+    /// Ignored textures belong to other masters, so are neither counted nor renumbered.
+    ///
+    /// Returns the number of indices in use.
+    ///
+    pub fn compact_textures(&mut self) -> usize {
+        let mut textures = self
+            .objects
+            .values_mut()
+            .filter_map(|object| match object {
+                TES3Object::LandscapeTexture(texture) if !texture.ignored() => Some(texture),
+                _ => None,
+            })
+            .collect_vec();
+
+        textures.sort_by_key(|texture| texture.index);
+
+        let mut index_remap = IndexRemap::new();
+
+        for (new_index, texture) in (0..).zip(&mut textures) {
+            let old_index = texture.index;
+            if old_index != new_index {
+                info!("Compacting texture index: ({old_index} -> {new_index}) {}", texture.id);
+                texture.index = new_index;
+                // Landscapes cannot refer to indices beyond the limit, so those need no remap.
+                if let (Ok(old_index), Ok(new_index)) = (u16::try_from(old_index), u16::try_from(new_index))
+                    && old_index < u16::MAX
+                {
+                    index_remap.insert(old_index + 1, new_index + 1);
+                }
+            }
+        }
+
+        let count = textures.len();
+
+        if !index_remap.is_empty() {
+            apply_index_remap(self, &index_remap);
+        }
+
+        count
+    }
+
+    /// The texture indices used by the landscapes of this plugin, see `landscape_index`.
+    ///
+    pub(crate) fn used_texture_indices(&self) -> HashSet<u16> {
+        self.cells
+            .exteriors
+            .values()
+            .filter_map(|exterior| exterior.landscape.as_ref())
+            .filter(|landscape| !landscape.ignored())
+            .flat_map(|landscape| landscape.texture_indices.data.as_flattened().iter().copied())
+            .collect()
+    }
+
+    /// The number of texture indices used by this plugin's own textures.
+    ///
+    /// This is one more than the highest index, so includes any unused indices below it.
+    ///
+    pub fn texture_indices(&self) -> u32 {
+        self.objects
+            .values()
+            .filter_map(|object| match object {
+                TES3Object::LandscapeTexture(texture) if !texture.ignored() => Some(texture.index + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
     }
 }
 
+/// The value that landscapes use to refer to `texture`, if they can refer to it at all.
+///
+/// This is one more than the texture's index, as 0 is reserved for the default texture.
+///
+pub(crate) fn landscape_index(texture: &LandscapeTexture) -> Option<u16> {
+    u16::try_from(texture.index).ok().filter(|&index| index < u16::MAX).map(|index| index + 1)
+}
+
 type IndexRemap = HashMap<u16, u16>;
 
 fn get_index_remap(this: &mut PluginData, master: &PluginData) -> Option<IndexRemap> {
@@ -52,17 +245,37 @@ fn get_index_remap(this: &mut PluginData, master: &PluginData) -> Option<IndexRe
             texture.index = new_index;
 
             // We need to +1 for remap lookups because 0 is reserved for "no texture".
-            // Also ensure we can fit into a u16 as that's what the landscapes expect.
-            assert!(old_index < 0xFFFF && new_index < 0xFFFF);
+            // New indices fit into a u16 as ensured by `required_indices`, while landscapes
+            // cannot refer to old indices that do not, so those need no remap.
+            let old_index = u16::try_from(old_index).ok().filter(|&i| i < u16::MAX)?;
 
-            #[allow(clippy::cast_possible_truncation)] // Ensured by the prior assert.
-            Some((old_index as u16 + 1, new_index as u16 + 1))
+            #[allow(clippy::cast_possible_truncation)] // Ensured by `required_indices`.
+            Some((old_index + 1, new_index as u16 + 1))
         })
         .collect();
 
     Some(index_remap)
 }
 
+/// The number of texture indices required to merge the textures of `this` into `master`.
+///
+fn required_indices(this: &PluginData, master: &PluginData) -> u32 {
+    let Some(next_index) = next_texture_index(master) else {
+        return next_texture_index(this).unwrap_or(0);
+    };
+
+    let added = this
+        .objects
+        .iter()
+        .filter(|(key, object)| {
+            matches!(object, TES3Object::LandscapeTexture(_))
+                && !matches!(master.objects.get(*key), Some(TES3Object::LandscapeTexture(_)))
+        })
+        .count();
+
+    next_index.saturating_add(u32::try_from(added).unwrap_or(u32::MAX))
+}
+
 fn next_texture_index(this: &PluginData) -> Option<u32> {
     this.objects
         .values()
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use tes3::esp::*;
 
 use crate::prelude::*;
@@ -8,15 +9,199 @@ pub trait RemoveDeleted {
     fn remove_deleted(&mut self);
 }
 
+/// Options for cleaning records that refer to deleted objects.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CleanOptions {
+    pub infos: DeletedInfoReferences,
+    /// The race given to NPCs whose race was deleted.
+    ///
+    /// If not specified their race is left as is, as the TESCS crashes on NPCs without a race.
+    /// When merging, the race must be defined by the master or one of its own masters.
+    pub fallback_race: Option<String>,
+    /// The class given to NPCs whose class was deleted.
+    ///
+    /// If not specified their class is cleared. When merging, the class must be defined by the
+    /// master or one of its own masters.
+    pub fallback_class: Option<String>,
+    /// The landscape texture that replaces deleted textures in landscapes.
+    ///
+    /// If not specified they are replaced with the default texture.
+    pub fallback_texture: Option<String>,
+    /// Convert deleted references owned by other masters into safe deletes.
+    ///
+    /// Deleting a reference of another master breaks savegames that refer to it, so instead it is
+    /// restored, blocked, moved far below its cell and scaled down, so it cannot be seen or used.
+    pub safe_delete: bool,
+    /// Remove the sound gens of deleted creatures, see `MergeOptions::remove_unused`.
+    ///
+    /// If not specified their creature is cleared, like any other field.
+    pub remove_sound_gens: bool,
+}
+
+/// How to handle dialogue `INFO`s whose speaker fields or filters refer to deleted objects.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum DeletedInfoReferences {
+    /// Clear the speaker fields and remove the filters.
+    ///
+    /// Note that this makes the `INFO` less restrictive, so it may be given by more speakers.
+    #[default]
+    Clean,
+    /// Remove the `INFO`.
+    Remove,
+    /// Leave the `INFO` as is.
+    Report,
+}
+
+/// The results of cleaning records that refer to deleted objects.
+///
+#[derive(Default, Serialize)]
+pub struct CleanReport {
+    /// The objects, interiors and topics that were deleted, and so removed.
+    pub deleted: Vec<RecordKey>,
+    /// Body parts that were removed along with their deleted race.
+    pub bodyparts: Vec<RecordKey>,
+    /// Sound gens that were removed along with their deleted creature.
+    pub sound_gens: Vec<RecordKey>,
+    pub fields: Vec<CleanedField>,
+    /// Fields of NPCs that were given a fallback, see `CleanOptions::fallback_race`.
+    pub fallbacks: Vec<SubstitutedField>,
+    pub infos: Vec<CleanedInfo>,
+    pub safe_deleted: Vec<SafeDeletedReference>,
+    pub textures: Vec<CleanedTextures>,
+}
+
+/// The texture indices of a landscape that referred to deleted textures, and were replaced.
+///
+#[derive(Serialize)]
+pub struct CleanedTextures {
+    pub grid: (i32, i32),
+    pub count: usize,
+    /// The texture they were replaced with, or `None` for the default texture.
+    pub replacement: Option<String>,
+}
+
+/// A deleted reference of another master that was converted into a safe delete.
+///
+#[derive(Serialize)]
+pub struct SafeDeletedReference {
+    pub cell: String,
+    pub id: String,
+    pub mast_index: u32,
+    pub refr_index: u32,
+}
+
+/// A field of a record that referred to a deleted object, and was cleaned.
+///
+#[derive(Serialize)]
+pub struct CleanedField {
+    /// Describes the record. (e.g. `NPC_ fargoth`)
+    pub record: String,
+    pub field: String,
+    /// The id of the deleted object.
+    pub id: String,
+}
+
+/// A field of a record that referred to a deleted object, and was given a fallback instead.
+///
+#[derive(Serialize)]
+pub struct SubstitutedField {
+    /// Describes the record. (e.g. `NPC_ fargoth`)
+    pub record: String,
+    pub field: String,
+    /// The id of the deleted object.
+    pub id: String,
+    pub fallback: String,
+}
+
+/// A dialogue `INFO` whose speaker fields or filters refer to deleted objects.
+///
+#[derive(Serialize)]
+pub struct CleanedInfo {
+    pub topic: String,
+    pub info: String,
+    /// The fields that refer to deleted objects. (e.g. `speaker_id 'fargoth'`)
+    pub fields: Vec<String>,
+    pub action: DeletedInfoReferences,
+}
+
+impl std::fmt::Display for CleanedTextures {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let replacement = self.replacement.as_deref().unwrap_or("the default texture");
+        write!(
+            f,
+            "LAND {:?}: {} deleted texture indices replaced with {replacement}",
+            self.grid, self.count
+        )
+    }
+}
+
+impl std::fmt::Display for CleanedField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} '{}' removed", self.record, self.field, self.id)
+    }
+}
+
+impl std::fmt::Display for SubstitutedField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {} '{}' replaced with '{}'",
+            self.record, self.field, self.id, self.fallback
+        )
+    }
+}
+
+impl CleanOptions {
+    pub const DEFAULT: Self = Self {
+        infos: DeletedInfoReferences::Clean,
+        fallback_race: None,
+        fallback_class: None,
+        fallback_texture: None,
+        safe_delete: false,
+        remove_sound_gens: false,
+    };
+}
+
+impl std::str::FromStr for DeletedInfoReferences {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "clean" => Ok(Self::Clean),
+            "remove" => Ok(Self::Remove),
+            "report" => Ok(Self::Report),
+            _ => bail!("Invalid mode, expected clean, remove or report: {s}"),
+        }
+    }
+}
+
 impl RemoveDeleted for PluginData {
     fn remove_deleted(&mut self) {
+        self.remove_deleted_with(&CleanOptions::DEFAULT);
+    }
+}
+
+impl PluginData {
+    /// Remove all objects that are marked as deleted, and clean the records that refer to them.
+    ///
+    pub fn remove_deleted_with(&mut self, options: &CleanOptions) -> CleanReport {
+        let mut report = CleanReport::default();
         let mut deletions = Deletions::new();
+        let mut deleted_textures = HashSet::new();
 
         self.objects
             .extract_if(|_, object| object.deleted())
-            .for_each(|((_, id), object)| {
+            .for_each(|(key, object)| {
                 info!("Removed deleted {} object: {}", object.tag_str(), object.editor_id());
-                deletions.entry(id.into()).or_default().insert(object.into());
+                if let TES3Object::LandscapeTexture(texture) = &object
+                    && let Some(index) = landscape_index(texture)
+                {
+                    deleted_textures.insert(index);
+                }
+                deletions.entry(key.1.clone().into()).or_default().insert(object.into());
+                report.deleted.push(RecordKey::Object(key));
             });
 
         self.cells
@@ -24,25 +209,148 @@ impl RemoveDeleted for PluginData {
             .extract_if(|_, interior| interior.cell.as_ref().is_some_and(<_>::deleted))
             .for_each(|(id, _)| {
                 info!("Removed deleted interior: {}", id.as_str());
-                deletions.entry(id).or_default().insert(DeletionFlags::CELL);
+                report.deleted.push(RecordKey::Cell(CellKey::interior(id.as_str())));
+                deletions.entry(id).or_default().insert(IdKinds::CELL);
             });
 
-        self.objects //
+        for (id, group) in &self.dialogues {
+            if group.dialogue.deleted() {
+                deletions
+                    .entry(id.clone().into())
+                    .or_default()
+                    .insert(IdKinds::DIALOGUE);
+                report.deleted.push(RecordKey::Dialogue(id.clone()));
+            }
+        }
+
+        report.deleted.sort_unstable();
+
+        // Body parts cannot be used without their race, so they are deleted along with it.
+        let orphaned_bodyparts = self
+            .objects
+            .extract_if(|_, object| match object {
+                TES3Object::Bodypart(bodypart) => deletions.intersects(&bodypart.race, IdKinds::RACE),
+                _ => false,
+            })
+            .collect_vec();
+        for (key, object) in orphaned_bodyparts {
+            info!("Removed body part of deleted race: {}", object.editor_id());
+            deletions
+                .entry(key.1.clone().into())
+                .or_default()
+                .insert(IdKinds::BODYPART);
+            report.bodyparts.push(RecordKey::Object(key));
+        }
+        report.bodyparts.sort_unstable();
+
+        // Once cleaned, sound gens of deleted creatures would apply to every creature instead.
+        let orphaned_sound_gens = self
+            .objects
+            .extract_if(|_, object| match object {
+                TES3Object::SoundGen(sound_gen) if options.remove_sound_gens => {
+                    deletions.intersects(&sound_gen.creature, IdKinds::PHYSICAL)
+                }
+                _ => false,
+            })
+            .collect_vec();
+        for (key, object) in orphaned_sound_gens {
+            info!("Removed sound gen of deleted creature: {}", object.editor_id());
+            deletions
+                .entry(key.1.clone().into())
+                .or_default()
+                .insert(IdKinds::SOUND_GEN);
+            report.sound_gens.push(RecordKey::Object(key));
+        }
+        report.sound_gens.sort_unstable();
+
+        // Substitute fallbacks first, so that the cleaning below leaves them as is.
+        for object in self.objects.values_mut() {
+            if let TES3Object::Npc(npc) = object {
+                report.fallbacks.extend(npc.apply_fallbacks(options, &deletions));
+            }
+        }
+        report
+            .fallbacks
+            .sort_unstable_by(|a, b| (&a.record, &a.field).cmp(&(&b.record, &b.field)));
+
+        report.fields = self
+            .objects
             .par_values_mut()
-            .for_each(|object| {
-                object.clean_deletions(&deletions);
-            });
+            .flat_map_iter(|object| {
+                let mut cleaner = Cleaner::new(&deletions, format!("{} {}", object.tag_str(), object.editor_id()));
+                object.visit_ids(&mut cleaner);
+                cleaner.cleaned
+            })
+            .collect();
+
+        if options.safe_delete {
+            report.safe_deleted = self.cells.safe_delete_references();
+        }
 
         // Note: We still need to run this code even if `deletions` is empty.
         // Because it also takes care of cleaning up deleted cell references.
-        self.cells //
+        let cleaned_cells: Vec<_> = self
+            .cells
             .par_iter_mut()
-            .for_each(|cell| {
-                cell.clean_deletions(&deletions);
-            });
+            .flat_map_iter(|cell| {
+                let mut cleaner = Cleaner::new(&deletions, format!("CELL '{}'", cell.editor_id()));
+                cell.visit_ids(&mut cleaner);
+                cleaner.cleaned
+            })
+            .collect();
+        report.fields.extend(cleaned_cells);
+
+        if !deleted_textures.is_empty() {
+            report.textures = self.clean_texture_indices(deleted_textures, options.fallback_texture.as_deref());
+        }
 
         self.cells.remove_deleted();
         self.dialogues.remove_deleted();
+
+        report.infos = clean_infos(&mut self.dialogues, &deletions, options.infos);
+
+        report
+    }
+}
+
+#[ext]
+impl Cells {
+    /// Convert the deleted references of other masters into safe deletes, see `CleanOptions`.
+    ///
+    fn safe_delete_references(&mut self) -> Vec<SafeDeletedReference> {
+        const SINK_DEPTH: f32 = -32768.0;
+        const MIN_SCALE: f32 = 0.5;
+
+        let mut converted = Vec::new();
+
+        for cell in self.iter_mut() {
+            if cell.ignored() {
+                continue;
+            }
+            let cell_name = cell.editor_id().into_owned();
+            for (&(mast_index, refr_index), reference) in &mut cell.references {
+                if mast_index == 0 || !reference.deleted() {
+                    continue;
+                }
+
+                reference.set_deleted(false);
+                // Morrowind has no flag for initially disabled references, so they are blocked instead.
+                reference.blocked = Some(1);
+                reference.translation[2] = SINK_DEPTH;
+                reference.scale = Some(MIN_SCALE);
+
+                info!("Safe deleted reference: {} ({mast_index}, {refr_index})", reference.id);
+
+                converted.push(SafeDeletedReference {
+                    cell: cell_name.clone(),
+                    id: reference.id.clone(),
+                    mast_index,
+                    refr_index,
+                });
+            }
+        }
+
+        converted
     }
 }
 
@@ -118,485 +426,230 @@ impl RemoveDeleted for HashMap<String, DialogueGroup> {
 
 /// Maps object ids of deleted objects to their types.
 ///
-type Deletions = HashMap<UString, DeletionFlags>;
-
-trait CleanDeletions {
-    /// Cleans all fields that refer to deleted objects.
-    ///
-    #[allow(unused_variables)]
-    fn clean_deletions(&mut self, deletions: &Deletions) {}
-}
+type Deletions = HashMap<UString, IdKinds>;
 
-impl CleanDeletions for TES3Object {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        delegate! {
-            match self {
-                inner => inner.clean_deletions(deletions),
+#[ext]
+impl Npc {
+    fn apply_fallbacks(&mut self, options: &CleanOptions, deletions: &Deletions) -> Vec<SubstitutedField> {
+        let mut substituted = Vec::new();
+        let fallbacks = [
+            ("race", &mut self.race, &options.fallback_race, IdKinds::RACE),
+            ("class", &mut self.class, &options.fallback_class, IdKinds::CLASS),
+        ];
+        for (field, id, fallback, flags) in fallbacks {
+            if let Some(fallback) = fallback
+                && deletions.intersects(id, flags)
+            {
+                if deletions.intersects(fallback, flags) {
+                    warn!("Fallback '{fallback}' for NPC '{}' was also deleted", self.id);
+                    continue;
+                }
+                substituted.push(SubstitutedField {
+                    record: format!("NPC_ {}", self.id),
+                    field: field.to_owned(),
+                    id: std::mem::replace(id, fallback.clone()),
+                    fallback: fallback.clone(),
+                });
             }
         }
+        substituted
     }
 }
 
-impl CleanDeletions for Race {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.spells.clean(DeletionFlags::SPELL, deletions);
-    }
-}
-
-impl CleanDeletions for SoundGen {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.creature.clean(DeletionFlags::PHYSICAL, deletions);
-        self.sound.clean(DeletionFlags::SOUND, deletions);
-    }
-}
-
-impl CleanDeletions for MagicEffect {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.bolt_sound.clean(DeletionFlags::SOUND, deletions);
-        self.cast_sound.clean(DeletionFlags::SOUND, deletions);
-        self.hit_sound.clean(DeletionFlags::SOUND, deletions);
-        self.area_sound.clean(DeletionFlags::SOUND, deletions);
-        self.cast_visual.clean(DeletionFlags::PHYSICAL, deletions);
-        self.bolt_visual.clean(DeletionFlags::PHYSICAL, deletions);
-        self.hit_visual.clean(DeletionFlags::PHYSICAL, deletions);
-        self.area_visual.clean(DeletionFlags::PHYSICAL, deletions);
-    }
-}
-
-impl CleanDeletions for Region {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.sleep_creature.clean(DeletionFlags::PHYSICAL, deletions);
-        self.sounds.clean(DeletionFlags::SOUND, deletions);
-    }
-}
-
-impl CleanDeletions for Birthsign {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.spells.clean(DeletionFlags::SPELL, deletions);
-    }
-}
-
-impl CleanDeletions for StartScript {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Door {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.open_sound.clean(DeletionFlags::SOUND, deletions);
-        self.close_sound.clean(DeletionFlags::SOUND, deletions);
-    }
-}
-
-impl CleanDeletions for MiscItem {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Weapon {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.enchanting.clean(DeletionFlags::ENCHANTING, deletions);
-    }
-}
-
-impl CleanDeletions for Container {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.inventory.clean(DeletionFlags::PHYSICAL, deletions);
-    }
-}
-
-impl CleanDeletions for Creature {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.inventory.clean(DeletionFlags::PHYSICAL, deletions);
-        self.spells.clean(DeletionFlags::SPELL, deletions);
-        self.ai_packages.clean_deletions(deletions);
-        self.travel_destinations.clean_deletions(deletions);
-    }
-}
-
-impl CleanDeletions for Light {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.sound.clean(DeletionFlags::SOUND, deletions);
-    }
-}
-
-impl CleanDeletions for Npc {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.inventory.clean(DeletionFlags::PHYSICAL, deletions);
-        self.spells.clean(DeletionFlags::SPELL, deletions);
-        self.ai_packages.clean_deletions(deletions);
-        self.travel_destinations.clean_deletions(deletions);
-        // self.race.clean(DeletionType::RACE, deletions); // Crashes TESCS if cleaned.
-        self.class.clean(DeletionFlags::CLASS, deletions);
-        self.faction.clean(DeletionFlags::FACTION, deletions);
-        self.head.clean(DeletionFlags::PHYSICAL, deletions);
-        self.hair.clean(DeletionFlags::PHYSICAL, deletions);
-    }
-}
-
-impl CleanDeletions for Armor {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.enchanting.clean(DeletionFlags::ENCHANTING, deletions);
-        self.biped_objects.clean_deletions(deletions);
-    }
-}
-
-impl CleanDeletions for Clothing {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.enchanting.clean(DeletionFlags::ENCHANTING, deletions);
-        self.biped_objects.clean_deletions(deletions);
-    }
-}
-
-impl CleanDeletions for RepairItem {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Activator {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Apparatus {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Lockpick {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Probe {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
-
-impl CleanDeletions for Ingredient {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
+#[ext]
+impl PluginData {
+    /// Replace the landscape texture indices of deleted textures.
+    ///
+    /// The `deleted` indices are those stored by landscapes, see `landscape_index`.
+    ///
+    fn clean_texture_indices(&mut self, mut deleted: HashSet<u16>, fallback: Option<&str>) -> Vec<CleanedTextures> {
+        let mut replacement = 0; // The default texture.
+        let mut replacement_id = None;
+
+        for object in self.objects.values() {
+            if let TES3Object::LandscapeTexture(texture) = object
+                && let Some(index) = landscape_index(texture)
+            {
+                // Another texture may have since been assigned the same index.
+                deleted.remove(&index);
+                if fallback.is_some_and(|id| texture.id.eq_ignore_ascii_case(id)) {
+                    replacement = index;
+                    replacement_id = Some(texture.id.clone());
+                }
+            }
+        }
 
-impl CleanDeletions for Book {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-        self.enchanting.clean(DeletionFlags::ENCHANTING, deletions);
-    }
-}
+        if let Some(fallback) = fallback
+            && replacement == 0
+        {
+            warn!("Fallback landscape texture '{fallback}' was not found, using the default texture");
+        }
 
-impl CleanDeletions for Alchemy {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.script.clean(DeletionFlags::SCRIPT, deletions);
-    }
-}
+        let mut cleaned = Vec::new();
 
-impl CleanDeletions for LeveledItem {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.items.clean(DeletionFlags::PHYSICAL, deletions);
-    }
-}
+        for (coords, exterior) in &mut self.cells.exteriors {
+            let Some(landscape) = exterior.landscape.as_mut() else {
+                continue;
+            };
+            let mut count = 0;
+            for index in landscape.texture_indices.data.as_flattened_mut() {
+                if deleted.contains(index) {
+                    *index = replacement;
+                    count += 1;
+                }
+            }
+            if count != 0 {
+                info!("Replaced {count} deleted texture indices in landscape {coords:?}");
+                cleaned.push(CleanedTextures {
+                    grid: *coords,
+                    count,
+                    replacement: replacement_id.clone(),
+                });
+            }
+        }
 
-impl CleanDeletions for LeveledCreature {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.creatures.clean(DeletionFlags::PHYSICAL, deletions);
+        cleaned.sort_unstable_by_key(|textures| textures.grid);
+        cleaned
     }
 }
 
-impl CleanDeletions for Cell {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.region.clean(DeletionFlags::REGION, deletions);
+/// Clean the speaker fields and filters of `INFO`s that refer to deleted objects.
+///
+fn clean_infos(
+    dialogues: &mut HashMap<String, DialogueGroup>,
+    deletions: &Deletions,
+    action: DeletedInfoReferences,
+) -> Vec<CleanedInfo> {
+    let mut cleaned = Vec::new();
+
+    for (topic, group) in dialogues.iter_mut().sorted_unstable_by(|a, b| a.0.cmp(b.0)) {
+        let mut removed = Vec::new();
+
+        for info in &mut group.infos {
+            // Other masters' INFOs are not part of the result, so they are left as is.
+            if info.ignored() {
+                continue;
+            }
 
-        // let cell_name = self.editor_id().into_owned();
+            let mut cleaner = Cleaner::new(deletions, format!("INFO {}", info.id));
+            cleaner.remove = action == DeletedInfoReferences::Clean;
+            info.visit_ids(&mut cleaner);
 
-        self.references.retain(|indices, reference| {
-            // Retain referances that are not local to the plugin.
-            if reference.mast_index != 0 {
-                return true;
+            if cleaner.cleaned.is_empty() {
+                continue;
             }
 
-            // Discard references that are explicitly marked as deleted.
-            if reference.deleted() {
-                return false;
-            }
+            let fields = cleaner
+                .cleaned
+                .into_iter()
+                .map(|field| format!("{} '{}'", field.field, field.id))
+                .collect_vec();
+
+            info!(
+                "{:?} INFO {} of topic '{topic}': {}",
+                action,
+                info.id,
+                fields.join(", ")
+            );
 
-            // Discard references that are implicitly deleted via object.
-            if deletions.intersects(&reference.id, DeletionFlags::PHYSICAL) {
-                info!("Removed deleted reference: {} {:?}", reference.id, indices);
-                return false;
+            if action == DeletedInfoReferences::Remove {
+                removed.push(info.id.clone());
             }
 
-            // Retain all non-deleted references.
-            true
-        });
-    }
-}
+            cleaned.push(CleanedInfo {
+                topic: topic.clone(),
+                info: info.id.clone(),
+                fields,
+                action,
+            });
+        }
 
-impl CleanDeletions for BipedObject {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.male_bodypart.clean(DeletionFlags::PHYSICAL, deletions);
-        self.female_bodypart.clean(DeletionFlags::PHYSICAL, deletions);
+        for id in removed {
+            group.remove_info(&id);
+        }
     }
-}
 
-impl CleanDeletions for TravelDestination {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.cell.clean(DeletionFlags::CELL, deletions);
-    }
+    cleaned
 }
 
-impl CleanDeletions for AiEscortPackage {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.target.clean(DeletionFlags::PHYSICAL, deletions);
-        self.cell.clean(DeletionFlags::CELL, deletions);
-    }
-}
+// ---------------------------------------------------------------------------
 
-impl CleanDeletions for AiFollowPackage {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.target.clean(DeletionFlags::PHYSICAL, deletions);
-        self.cell.clean(DeletionFlags::CELL, deletions);
+/// Cleans the fields of a single record that refer to deleted objects.
+///
+struct Cleaner<'a> {
+    deletions: &'a Deletions,
+    /// Describes the record being cleaned. (e.g. `NPC_ fargoth`)
+    base: String,
+    /// Describes the record or reference whose fields are being visited.
+    record: String,
+    /// Whether fields are cleaned, or only tracked.
+    remove: bool,
+    cleaned: Vec<CleanedField>,
+}
+
+impl<'a> Cleaner<'a> {
+    fn new(deletions: &'a Deletions, record: String) -> Self {
+        Self {
+            deletions,
+            base: record.clone(),
+            record,
+            remove: true,
+            cleaned: Vec::new(),
+        }
     }
-}
 
-impl CleanDeletions for AiActivatePackage {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        self.target.clean(DeletionFlags::PHYSICAL, deletions);
+    fn push(&mut self, field: &str, id: &str) {
+        self.cleaned.push(CleanedField {
+            record: self.record.clone(),
+            field: field.to_owned(),
+            id: id.to_owned(),
+        });
     }
 }
 
-impl CleanDeletions for AiPackage {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        match self {
-            AiPackage::Travel(package) => package.clean_deletions(deletions),
-            AiPackage::Wander(package) => package.clean_deletions(deletions),
-            AiPackage::Escort(package) => package.clean_deletions(deletions),
-            AiPackage::Follow(package) => package.clean_deletions(deletions),
-            AiPackage::Activate(package) => package.clean_deletions(deletions),
+impl IdVisitor for Cleaner<'_> {
+    fn visit(&mut self, field: &str, kinds: IdKinds, id: &mut String) -> bool {
+        if !self.deletions.intersects(id, kinds) {
+            return true;
         }
+        self.push(field, id);
+        !self.remove
     }
-}
 
-impl<T: CleanDeletions> CleanDeletions for Vec<T> {
-    fn clean_deletions(&mut self, deletions: &Deletions) {
-        for item in self {
-            item.clean_deletions(deletions);
-        }
+    fn visit_required(&mut self, _: &str, _: IdKinds, _: &mut String) {
+        // Left as is, see `CleanOptions::fallback_race`.
     }
-}
 
-impl CleanDeletions for Header {}
-impl CleanDeletions for GameSetting {}
-impl CleanDeletions for GlobalVariable {}
-impl CleanDeletions for Class {}
-impl CleanDeletions for Faction {}
-impl CleanDeletions for Sound {}
-impl CleanDeletions for Skill {}
-impl CleanDeletions for Script {}
-impl CleanDeletions for LandscapeTexture {}
-impl CleanDeletions for Spell {}
-impl CleanDeletions for Static {}
-impl CleanDeletions for Bodypart {}
-impl CleanDeletions for Enchanting {}
-impl CleanDeletions for Landscape {}
-impl CleanDeletions for PathGrid {}
-impl CleanDeletions for Dialogue {}
-impl CleanDeletions for DialogueInfo {}
-impl CleanDeletions for AiTravelPackage {}
-impl CleanDeletions for AiWanderPackage {}
-
-// ---------------------------------------------------------------------------
-
-bitflags::bitflags! {
-    #[derive(Clone, Copy, Default)]
-    struct DeletionFlags: u64 {
-        const HEADER            = 1 << 0;
-        const GAME_SETTING      = 1 << 1;
-        const GLOBAL_VARIABLE   = 1 << 2;
-        const CLASS             = 1 << 3;
-        const FACTION           = 1 << 4;
-        const RACE              = 1 << 5;
-        const SOUND             = 1 << 6;
-        const SOUND_GEN         = 1 << 7;
-        const SKILL             = 1 << 8;
-        const MAGIC_EFFECT      = 1 << 9;
-        const SCRIPT            = 1 << 10;
-        const REGION            = 1 << 11;
-        const BIRTHSIGN         = 1 << 12;
-        const START_SCRIPT      = 1 << 13;
-        const LANDSCAPE_TEXTURE = 1 << 14;
-        const SPELL             = 1 << 15;
-        const STATIC            = 1 << 16;
-        const DOOR              = 1 << 17;
-        const MISC_ITEM         = 1 << 18;
-        const WEAPON            = 1 << 19;
-        const CONTAINER         = 1 << 20;
-        const CREATURE          = 1 << 21;
-        const BODYPART          = 1 << 22;
-        const LIGHT             = 1 << 23;
-        const ENCHANTING        = 1 << 24;
-        const NPC               = 1 << 25;
-        const ARMOR             = 1 << 26;
-        const CLOTHING          = 1 << 27;
-        const REPAIR_ITEM       = 1 << 28;
-        const ACTIVATOR         = 1 << 29;
-        const APPARATUS         = 1 << 30;
-        const LOCKPICK          = 1 << 31;
-        const PROBE             = 1 << 32;
-        const INGREDIENT        = 1 << 33;
-        const BOOK              = 1 << 34;
-        const ALCHEMY           = 1 << 35;
-        const LEVELED_ITEM      = 1 << 36;
-        const LEVELED_CREATURE  = 1 << 37;
-        const CELL              = 1 << 38;
-        const LANDSCAPE         = 1 << 39;
-        const PATH_GRID         = 1 << 40;
-        const DIALOGUE          = 1 << 41;
-        const DIALOGUE_INFO     = 1 << 42;
-
-        const PHYSICAL = (
-            DeletionFlags::ACTIVATOR.bits()
-            | DeletionFlags::ALCHEMY.bits()
-            | DeletionFlags::APPARATUS.bits()
-            | DeletionFlags::ARMOR.bits()
-            | DeletionFlags::BODYPART.bits()
-            | DeletionFlags::BOOK.bits()
-            | DeletionFlags::CLOTHING.bits()
-            | DeletionFlags::CONTAINER.bits()
-            | DeletionFlags::CREATURE.bits()
-            | DeletionFlags::DOOR.bits()
-            | DeletionFlags::INGREDIENT.bits()
-            | DeletionFlags::LEVELED_CREATURE.bits()
-            | DeletionFlags::LEVELED_ITEM.bits()
-            | DeletionFlags::LIGHT.bits()
-            | DeletionFlags::LOCKPICK.bits()
-            | DeletionFlags::MISC_ITEM.bits()
-            | DeletionFlags::NPC.bits()
-            | DeletionFlags::PROBE.bits()
-            | DeletionFlags::REPAIR_ITEM.bits()
-            | DeletionFlags::STATIC.bits()
-            | DeletionFlags::WEAPON.bits()
-        );
-    }
-}
+    fn visit_reference(&mut self, indices: (u32, u32), reference: &mut Reference) -> bool {
+        self.record = format!("{} REFR '{}' {indices:?}", self.base, reference.id);
 
-impl From<TES3Object> for DeletionFlags {
-    fn from(object: TES3Object) -> Self {
-        match object {
-            TES3Object::Header(_) => DeletionFlags::HEADER,
-            TES3Object::GameSetting(_) => DeletionFlags::GAME_SETTING,
-            TES3Object::GlobalVariable(_) => DeletionFlags::GLOBAL_VARIABLE,
-            TES3Object::Class(_) => DeletionFlags::CLASS,
-            TES3Object::Faction(_) => DeletionFlags::FACTION,
-            TES3Object::Race(_) => DeletionFlags::RACE,
-            TES3Object::Sound(_) => DeletionFlags::SOUND,
-            TES3Object::SoundGen(_) => DeletionFlags::SOUND_GEN,
-            TES3Object::Skill(_) => DeletionFlags::SKILL,
-            TES3Object::MagicEffect(_) => DeletionFlags::MAGIC_EFFECT,
-            TES3Object::Script(_) => DeletionFlags::SCRIPT,
-            TES3Object::Region(_) => DeletionFlags::REGION,
-            TES3Object::Birthsign(_) => DeletionFlags::BIRTHSIGN,
-            TES3Object::StartScript(_) => DeletionFlags::START_SCRIPT,
-            TES3Object::LandscapeTexture(_) => DeletionFlags::LANDSCAPE_TEXTURE,
-            TES3Object::Spell(_) => DeletionFlags::SPELL,
-            TES3Object::Static(_) => DeletionFlags::STATIC,
-            TES3Object::Door(_) => DeletionFlags::DOOR,
-            TES3Object::MiscItem(_) => DeletionFlags::MISC_ITEM,
-            TES3Object::Weapon(_) => DeletionFlags::WEAPON,
-            TES3Object::Container(_) => DeletionFlags::CONTAINER,
-            TES3Object::Creature(_) => DeletionFlags::CREATURE,
-            TES3Object::Bodypart(_) => DeletionFlags::BODYPART,
-            TES3Object::Light(_) => DeletionFlags::LIGHT,
-            TES3Object::Enchanting(_) => DeletionFlags::ENCHANTING,
-            TES3Object::Npc(_) => DeletionFlags::NPC,
-            TES3Object::Armor(_) => DeletionFlags::ARMOR,
-            TES3Object::Clothing(_) => DeletionFlags::CLOTHING,
-            TES3Object::RepairItem(_) => DeletionFlags::REPAIR_ITEM,
-            TES3Object::Activator(_) => DeletionFlags::ACTIVATOR,
-            TES3Object::Apparatus(_) => DeletionFlags::APPARATUS,
-            TES3Object::Lockpick(_) => DeletionFlags::LOCKPICK,
-            TES3Object::Probe(_) => DeletionFlags::PROBE,
-            TES3Object::Ingredient(_) => DeletionFlags::INGREDIENT,
-            TES3Object::Book(_) => DeletionFlags::BOOK,
-            TES3Object::Alchemy(_) => DeletionFlags::ALCHEMY,
-            TES3Object::LeveledItem(_) => DeletionFlags::LEVELED_ITEM,
-            TES3Object::LeveledCreature(_) => DeletionFlags::LEVELED_CREATURE,
-            TES3Object::Cell(_) => DeletionFlags::CELL,
-            TES3Object::Landscape(_) => DeletionFlags::LANDSCAPE,
-            TES3Object::PathGrid(_) => DeletionFlags::PATH_GRID,
-            TES3Object::Dialogue(_) => DeletionFlags::DIALOGUE,
-            TES3Object::DialogueInfo(_) => DeletionFlags::DIALOGUE_INFO,
+        // The rank is meaningless once its faction is cleaned.
+        if self.remove
+            && let Some(faction) = &reference.owner_faction
+            && self.deletions.intersects(faction, IdKinds::FACTION)
+        {
+            reference.owner_faction_rank = None;
         }
-    }
-}
 
-// ---------------------------------------------------------------------------
-
-use std::borrow::Borrow;
-
-#[ext]
-impl String {
-    fn clean(&mut self, flags: DeletionFlags, deletions: &Deletions) {
-        if deletions.intersects(self, flags) {
-            self.clear();
+        // Retain referances that are not local to the plugin.
+        if reference.mast_index != 0 {
+            return true;
         }
-    }
-}
 
-#[ext]
-impl Option<String> {
-    fn clean(&mut self, flags: DeletionFlags, deletions: &Deletions) {
-        if let Some(id) = self.as_ref()
-            && deletions.intersects(id, flags)
-        {
-            *self = None;
+        // Discard references that are explicitly marked as deleted.
+        if reference.deleted() {
+            return false;
         }
-    }
-}
-
-#[ext]
-impl<S> Vec<S>
-where
-    S: Borrow<str>,
-{
-    fn clean(&mut self, flags: DeletionFlags, deletions: &Deletions) {
-        self.retain(|id| !deletions.intersects(id.borrow(), flags));
-    }
-}
 
-#[ext]
-impl<S, T> Vec<(S, T)>
-where
-    S: Borrow<str>,
-{
-    fn clean(&mut self, flags: DeletionFlags, deletions: &Deletions) {
-        self.retain(|(id, _)| !deletions.intersects(id.borrow(), flags));
-    }
-}
+        // Discard references that are implicitly deleted via object.
+        if self.deletions.intersects(&reference.id, IdKinds::PHYSICAL) {
+            info!("Removed deleted reference: {} {:?}", reference.id, indices);
+            self.push("id", &reference.id);
+            return false;
+        }
 
-#[ext]
-impl<S, T> Vec<(T, S)>
-where
-    S: Borrow<str>,
-{
-    fn clean(&mut self, flags: DeletionFlags, deletions: &Deletions) {
-        self.retain(|(_, id)| !deletions.intersects(id.borrow(), flags));
+        // Retain all non-deleted references.
+        true
     }
 }
 
@@ -604,7 +657,7 @@ where
 
 #[ext]
 impl Deletions {
-    fn intersects(&self, id: &str, flags: DeletionFlags) -> bool {
+    fn intersects(&self, id: &str, flags: IdKinds) -> bool {
         self.get(id.as_uncased()) //
             .is_some_and(|deletion| deletion.intersects(flags))
     }
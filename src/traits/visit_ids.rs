@@ -0,0 +1,668 @@
+use tes3::esp::*;
+
+use crate::prelude::*;
+
+/// Visits every field of a record that refers to another object by id.
+///
+/// This is the single source of knowledge about which fields refer to which types of objects,
+/// used to clean references to deleted objects, replace ids, and find where ids are used.
+///
+pub trait VisitIds {
+    /// Visit the id fields, which the visitor may change or remove.
+    ///
+    #[allow(unused_variables)]
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {}
+
+    /// Read the id fields, without changing the record.
+    ///
+    #[allow(unused_variables)]
+    fn read_ids(&self, reader: &mut impl IdReader) {}
+}
+
+pub trait IdVisitor {
+    /// Visit a field that refers to an object of the given kinds.
+    ///
+    /// Returns `false` if the id should be removed. Depending on the field this either clears
+    /// it, or removes the entry that contains it. (e.g. an inventory item)
+    ///
+    fn visit(&mut self, field: &str, kinds: IdKinds, id: &mut String) -> bool;
+
+    /// Visit a field that cannot be removed, as the TESCS requires it to have a value.
+    ///
+    fn visit_required(&mut self, field: &str, kinds: IdKinds, id: &mut String) {
+        self.visit(field, kinds, id);
+    }
+
+    /// Visit a field that holds at most `max_len` bytes, as longer ids would be truncated when saved.
+    ///
+    fn visit_limited(&mut self, field: &str, kinds: IdKinds, id: &mut String, max_len: usize) -> bool {
+        let _ = max_len;
+        self.visit(field, kinds, id)
+    }
+
+    /// Visit a reference of a cell, before visiting its fields.
+    ///
+    /// Returns `false` if the reference should be removed. By default its base object is visited
+    /// like any other field.
+    ///
+    fn visit_reference(&mut self, indices: (u32, u32), reference: &mut Reference) -> bool {
+        let _ = indices;
+        self.visit("id", IdKinds::PHYSICAL, &mut reference.id)
+    }
+}
+
+pub trait IdReader {
+    /// Read a field that refers to an object of the given kinds.
+    ///
+    fn read(&mut self, field: &str, kinds: IdKinds, id: &str);
+
+    /// Read a reference of a cell, before reading its fields.
+    ///
+    /// By default its base object is read like any other field.
+    ///
+    fn read_reference(&mut self, indices: (u32, u32), reference: &Reference) {
+        let _ = indices;
+        self.read("id", IdKinds::PHYSICAL, &reference.id);
+    }
+}
+
+/// Implements both visits for records whose ids are all held by plain fields.
+///
+/// Each field is listed with the kinds of objects it refers to, and is named after itself. Fields
+/// marked `#[required]` cannot be removed, and fields without kinds are visited in turn.
+///
+macro_rules! impl_visit_ids {
+    ($($type:ty { $($(#[$required:ident])? $field:ident $(: $kinds:ident)?),* $(,)? })*) => {
+        $(
+            impl VisitIds for $type {
+                fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+                    $(impl_visit_ids!(@visit self.$field, $field, [$($required)?], [$($kinds)?], visitor);)*
+                }
+
+                fn read_ids(&self, reader: &mut impl IdReader) {
+                    $(impl_visit_ids!(@read self.$field, $field, [$($kinds)?], reader);)*
+                }
+            }
+        )*
+    };
+    (@visit $value:expr, $field:ident, [], [], $visitor:ident) => {
+        $value.visit_ids($visitor)
+    };
+    (@visit $value:expr, $field:ident, [], [$kinds:ident], $visitor:ident) => {
+        $value.visit(stringify!($field), IdKinds::$kinds, $visitor)
+    };
+    (@visit $value:expr, $field:ident, [required], [$kinds:ident], $visitor:ident) => {
+        $value.visit_required(stringify!($field), IdKinds::$kinds, $visitor)
+    };
+    (@read $value:expr, $field:ident, [], $reader:ident) => {
+        $value.read_ids($reader)
+    };
+    (@read $value:expr, $field:ident, [$kinds:ident], $reader:ident) => {
+        $value.read(stringify!($field), IdKinds::$kinds, $reader)
+    };
+}
+
+impl VisitIds for TES3Object {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        delegate! {
+            match self {
+                inner => inner.visit_ids(visitor),
+            }
+        }
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        delegate! {
+            match self {
+                inner => inner.read_ids(reader),
+            }
+        }
+    }
+}
+
+impl_visit_ids! {
+    Race {
+        spells: SPELL,
+    }
+    SoundGen {
+        creature: PHYSICAL,
+        sound: SOUND,
+    }
+    MagicEffect {
+        bolt_sound: SOUND,
+        cast_sound: SOUND,
+        hit_sound: SOUND,
+        area_sound: SOUND,
+        cast_visual: PHYSICAL,
+        bolt_visual: PHYSICAL,
+        hit_visual: PHYSICAL,
+        area_visual: PHYSICAL,
+    }
+    Region {
+        sleep_creature: PHYSICAL,
+        sounds: SOUND,
+    }
+    Birthsign {
+        spells: SPELL,
+    }
+    StartScript {
+        script: SCRIPT,
+    }
+    Door {
+        script: SCRIPT,
+        open_sound: SOUND,
+        close_sound: SOUND,
+    }
+    MiscItem {
+        script: SCRIPT,
+    }
+    Weapon {
+        script: SCRIPT,
+        enchanting: ENCHANTING,
+    }
+    Container {
+        script: SCRIPT,
+        inventory: PHYSICAL,
+    }
+    Creature {
+        script: SCRIPT,
+        inventory: PHYSICAL,
+        spells: SPELL,
+        ai_packages,
+        travel_destinations,
+    }
+    Light {
+        script: SCRIPT,
+        sound: SOUND,
+    }
+    Npc {
+        script: SCRIPT,
+        inventory: PHYSICAL,
+        spells: SPELL,
+        ai_packages,
+        travel_destinations,
+        #[required] race: RACE, // Crashes TESCS if cleared.
+        class: CLASS,
+        faction: FACTION,
+        head: PHYSICAL,
+        hair: PHYSICAL,
+    }
+    Armor {
+        script: SCRIPT,
+        enchanting: ENCHANTING,
+        biped_objects,
+    }
+    Clothing {
+        script: SCRIPT,
+        enchanting: ENCHANTING,
+        biped_objects,
+    }
+    RepairItem {
+        script: SCRIPT,
+    }
+    Activator {
+        script: SCRIPT,
+    }
+    Apparatus {
+        script: SCRIPT,
+    }
+    Lockpick {
+        script: SCRIPT,
+    }
+    Probe {
+        script: SCRIPT,
+    }
+    Ingredient {
+        script: SCRIPT,
+    }
+    Book {
+        script: SCRIPT,
+        enchanting: ENCHANTING,
+    }
+    Alchemy {
+        script: SCRIPT,
+    }
+    LeveledItem {
+        items: PHYSICAL,
+    }
+    LeveledCreature {
+        creatures: PHYSICAL,
+    }
+    BipedObject {
+        male_bodypart: PHYSICAL,
+        female_bodypart: PHYSICAL,
+    }
+    TravelDestination {
+        cell: CELL,
+    }
+    AiEscortPackage {
+        target: PHYSICAL,
+        cell: CELL,
+    }
+    AiFollowPackage {
+        target: PHYSICAL,
+        cell: CELL,
+    }
+    AiActivatePackage {
+        target: PHYSICAL,
+    }
+    Bodypart {
+        #[required] race: RACE,
+    }
+}
+
+impl VisitIds for Faction {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        self.reactions
+            .retain_mut(|reaction| visitor.visit("reactions", IdKinds::FACTION, &mut reaction.faction));
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        for reaction in &self.reactions {
+            reader.read("reactions", IdKinds::FACTION, &reaction.faction);
+        }
+    }
+}
+
+impl VisitIds for Cell {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        self.region.visit("region", IdKinds::REGION, visitor);
+
+        self.references.retain(|&indices, reference| {
+            if !visitor.visit_reference(indices, reference) {
+                return false;
+            }
+            reference.visit_ids(visitor);
+            true
+        });
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        self.region.read("region", IdKinds::REGION, reader);
+
+        for (&indices, reference) in &self.references {
+            reader.read_reference(indices, reference);
+            reference.read_ids(reader);
+        }
+    }
+}
+
+impl VisitIds for Reference {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        self.owner.visit("owner", IdKinds::NPC, visitor);
+        self.owner_global.visit("owner_global", IdKinds::GLOBAL_VARIABLE, visitor);
+        self.owner_faction.visit("owner_faction", IdKinds::FACTION, visitor);
+        self.key.visit("key", IdKinds::MISC_ITEM, visitor);
+        self.trap.visit("trap", IdKinds::SPELL, visitor);
+        self.soul.visit("soul", IdKinds::CREATURE, visitor);
+
+        // An empty cell name would teleport to the exterior instead, so remove the destination.
+        if let Some(destination) = &mut self.destination
+            && !visitor.visit("destination", IdKinds::CELL, &mut destination.cell)
+        {
+            self.destination = None;
+        }
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        self.owner.read("owner", IdKinds::NPC, reader);
+        self.owner_global.read("owner_global", IdKinds::GLOBAL_VARIABLE, reader);
+        self.owner_faction.read("owner_faction", IdKinds::FACTION, reader);
+        self.key.read("key", IdKinds::MISC_ITEM, reader);
+        self.trap.read("trap", IdKinds::SPELL, reader);
+        self.soul.read("soul", IdKinds::CREATURE, reader);
+
+        if let Some(destination) = &self.destination {
+            reader.read("destination", IdKinds::CELL, &destination.cell);
+        }
+    }
+}
+
+impl VisitIds for DialogueInfo {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        self.speaker_id.visit("speaker_id", IdKinds::PHYSICAL, visitor);
+        self.speaker_race.visit("speaker_race", IdKinds::RACE, visitor);
+        self.speaker_class.visit("speaker_class", IdKinds::CLASS, visitor);
+        self.speaker_faction.visit("speaker_faction", IdKinds::FACTION, visitor);
+        self.speaker_cell.visit("speaker_cell", IdKinds::CELL, visitor);
+        self.player_faction.visit("player_faction", IdKinds::FACTION, visitor);
+        self.filters.retain_mut(|filter| match filter_kinds(filter.filter_type) {
+            Some(kinds) => visitor.visit("filters", kinds, &mut filter.id),
+            None => true,
+        });
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        self.speaker_id.read("speaker_id", IdKinds::PHYSICAL, reader);
+        self.speaker_race.read("speaker_race", IdKinds::RACE, reader);
+        self.speaker_class.read("speaker_class", IdKinds::CLASS, reader);
+        self.speaker_faction.read("speaker_faction", IdKinds::FACTION, reader);
+        self.speaker_cell.read("speaker_cell", IdKinds::CELL, reader);
+        self.player_faction.read("player_faction", IdKinds::FACTION, reader);
+        for filter in &self.filters {
+            if let Some(kinds) = filter_kinds(filter.filter_type) {
+                reader.read("filters", kinds, &filter.id);
+            }
+        }
+    }
+}
+
+/// The types of objects a filter may refer to by id.
+///
+const fn filter_kinds(filter_type: FilterType) -> Option<IdKinds> {
+    match filter_type {
+        FilterType::Global => Some(IdKinds::GLOBAL_VARIABLE),
+        FilterType::Journal => Some(IdKinds::DIALOGUE),
+        FilterType::Item | FilterType::Dead | FilterType::NotId => Some(IdKinds::PHYSICAL),
+        FilterType::NotFaction => Some(IdKinds::FACTION),
+        FilterType::NotClass => Some(IdKinds::CLASS),
+        FilterType::NotRace => Some(IdKinds::RACE),
+        FilterType::NotCell => Some(IdKinds::CELL),
+        _ => None,
+    }
+}
+
+impl VisitIds for AiPackage {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        match self {
+            AiPackage::Travel(package) => package.visit_ids(visitor),
+            AiPackage::Wander(package) => package.visit_ids(visitor),
+            AiPackage::Escort(package) => package.visit_ids(visitor),
+            AiPackage::Follow(package) => package.visit_ids(visitor),
+            AiPackage::Activate(package) => package.visit_ids(visitor),
+        }
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        match self {
+            AiPackage::Travel(package) => package.read_ids(reader),
+            AiPackage::Wander(package) => package.read_ids(reader),
+            AiPackage::Escort(package) => package.read_ids(reader),
+            AiPackage::Follow(package) => package.read_ids(reader),
+            AiPackage::Activate(package) => package.read_ids(reader),
+        }
+    }
+}
+
+impl<T: VisitIds> VisitIds for Vec<T> {
+    fn visit_ids(&mut self, visitor: &mut impl IdVisitor) {
+        for item in self {
+            item.visit_ids(visitor);
+        }
+    }
+
+    fn read_ids(&self, reader: &mut impl IdReader) {
+        for item in self {
+            item.read_ids(reader);
+        }
+    }
+}
+
+impl VisitIds for Header {}
+impl VisitIds for GameSetting {}
+impl VisitIds for GlobalVariable {}
+impl VisitIds for Class {}
+impl VisitIds for Sound {}
+impl VisitIds for Skill {}
+impl VisitIds for Script {}
+impl VisitIds for LandscapeTexture {}
+impl VisitIds for Spell {} // Effects refer to magic effects by index.
+impl VisitIds for Static {}
+impl VisitIds for Enchanting {}
+impl VisitIds for Landscape {}
+impl VisitIds for PathGrid {}
+impl VisitIds for Dialogue {}
+impl VisitIds for AiTravelPackage {}
+impl VisitIds for AiWanderPackage {}
+
+// ---------------------------------------------------------------------------
+
+bitflags::bitflags! {
+    #[derive(Clone, Copy, Default)]
+    /// The types of objects that an id may refer to.
+    ///
+    pub struct IdKinds: u64 {
+        const HEADER            = 1 << 0;
+        const GAME_SETTING      = 1 << 1;
+        const GLOBAL_VARIABLE   = 1 << 2;
+        const CLASS             = 1 << 3;
+        const FACTION           = 1 << 4;
+        const RACE              = 1 << 5;
+        const SOUND             = 1 << 6;
+        const SOUND_GEN         = 1 << 7;
+        const SKILL             = 1 << 8;
+        const MAGIC_EFFECT      = 1 << 9;
+        const SCRIPT            = 1 << 10;
+        const REGION            = 1 << 11;
+        const BIRTHSIGN         = 1 << 12;
+        const START_SCRIPT      = 1 << 13;
+        const LANDSCAPE_TEXTURE = 1 << 14;
+        const SPELL             = 1 << 15;
+        const STATIC            = 1 << 16;
+        const DOOR              = 1 << 17;
+        const MISC_ITEM         = 1 << 18;
+        const WEAPON            = 1 << 19;
+        const CONTAINER         = 1 << 20;
+        const CREATURE          = 1 << 21;
+        const BODYPART          = 1 << 22;
+        const LIGHT             = 1 << 23;
+        const ENCHANTING        = 1 << 24;
+        const NPC               = 1 << 25;
+        const ARMOR             = 1 << 26;
+        const CLOTHING          = 1 << 27;
+        const REPAIR_ITEM       = 1 << 28;
+        const ACTIVATOR         = 1 << 29;
+        const APPARATUS         = 1 << 30;
+        const LOCKPICK          = 1 << 31;
+        const PROBE             = 1 << 32;
+        const INGREDIENT        = 1 << 33;
+        const BOOK              = 1 << 34;
+        const ALCHEMY           = 1 << 35;
+        const LEVELED_ITEM      = 1 << 36;
+        const LEVELED_CREATURE  = 1 << 37;
+        const CELL              = 1 << 38;
+        const LANDSCAPE         = 1 << 39;
+        const PATH_GRID         = 1 << 40;
+        const DIALOGUE          = 1 << 41;
+        const DIALOGUE_INFO     = 1 << 42;
+
+        const PHYSICAL = (
+            IdKinds::ACTIVATOR.bits()
+            | IdKinds::ALCHEMY.bits()
+            | IdKinds::APPARATUS.bits()
+            | IdKinds::ARMOR.bits()
+            | IdKinds::BODYPART.bits()
+            | IdKinds::BOOK.bits()
+            | IdKinds::CLOTHING.bits()
+            | IdKinds::CONTAINER.bits()
+            | IdKinds::CREATURE.bits()
+            | IdKinds::DOOR.bits()
+            | IdKinds::INGREDIENT.bits()
+            | IdKinds::LEVELED_CREATURE.bits()
+            | IdKinds::LEVELED_ITEM.bits()
+            | IdKinds::LIGHT.bits()
+            | IdKinds::LOCKPICK.bits()
+            | IdKinds::MISC_ITEM.bits()
+            | IdKinds::NPC.bits()
+            | IdKinds::PROBE.bits()
+            | IdKinds::REPAIR_ITEM.bits()
+            | IdKinds::STATIC.bits()
+            | IdKinds::WEAPON.bits()
+        );
+    }
+}
+
+impl From<TES3Object> for IdKinds {
+    fn from(object: TES3Object) -> Self {
+        match object {
+            TES3Object::Header(_) => IdKinds::HEADER,
+            TES3Object::GameSetting(_) => IdKinds::GAME_SETTING,
+            TES3Object::GlobalVariable(_) => IdKinds::GLOBAL_VARIABLE,
+            TES3Object::Class(_) => IdKinds::CLASS,
+            TES3Object::Faction(_) => IdKinds::FACTION,
+            TES3Object::Race(_) => IdKinds::RACE,
+            TES3Object::Sound(_) => IdKinds::SOUND,
+            TES3Object::SoundGen(_) => IdKinds::SOUND_GEN,
+            TES3Object::Skill(_) => IdKinds::SKILL,
+            TES3Object::MagicEffect(_) => IdKinds::MAGIC_EFFECT,
+            TES3Object::Script(_) => IdKinds::SCRIPT,
+            TES3Object::Region(_) => IdKinds::REGION,
+            TES3Object::Birthsign(_) => IdKinds::BIRTHSIGN,
+            TES3Object::StartScript(_) => IdKinds::START_SCRIPT,
+            TES3Object::LandscapeTexture(_) => IdKinds::LANDSCAPE_TEXTURE,
+            TES3Object::Spell(_) => IdKinds::SPELL,
+            TES3Object::Static(_) => IdKinds::STATIC,
+            TES3Object::Door(_) => IdKinds::DOOR,
+            TES3Object::MiscItem(_) => IdKinds::MISC_ITEM,
+            TES3Object::Weapon(_) => IdKinds::WEAPON,
+            TES3Object::Container(_) => IdKinds::CONTAINER,
+            TES3Object::Creature(_) => IdKinds::CREATURE,
+            TES3Object::Bodypart(_) => IdKinds::BODYPART,
+            TES3Object::Light(_) => IdKinds::LIGHT,
+            TES3Object::Enchanting(_) => IdKinds::ENCHANTING,
+            TES3Object::Npc(_) => IdKinds::NPC,
+            TES3Object::Armor(_) => IdKinds::ARMOR,
+            TES3Object::Clothing(_) => IdKinds::CLOTHING,
+            TES3Object::RepairItem(_) => IdKinds::REPAIR_ITEM,
+            TES3Object::Activator(_) => IdKinds::ACTIVATOR,
+            TES3Object::Apparatus(_) => IdKinds::APPARATUS,
+            TES3Object::Lockpick(_) => IdKinds::LOCKPICK,
+            TES3Object::Probe(_) => IdKinds::PROBE,
+            TES3Object::Ingredient(_) => IdKinds::INGREDIENT,
+            TES3Object::Book(_) => IdKinds::BOOK,
+            TES3Object::Alchemy(_) => IdKinds::ALCHEMY,
+            TES3Object::LeveledItem(_) => IdKinds::LEVELED_ITEM,
+            TES3Object::LeveledCreature(_) => IdKinds::LEVELED_CREATURE,
+            TES3Object::Cell(_) => IdKinds::CELL,
+            TES3Object::Landscape(_) => IdKinds::LANDSCAPE,
+            TES3Object::PathGrid(_) => IdKinds::PATH_GRID,
+            TES3Object::Dialogue(_) => IdKinds::DIALOGUE,
+            TES3Object::DialogueInfo(_) => IdKinds::DIALOGUE_INFO,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+
+/// The types of fields that hold ids.
+///
+pub trait IdField {
+    fn id(&self) -> &str;
+
+    fn id_mut(&mut self) -> &mut String;
+
+    /// Visit the id, along with the length limit of this field if it has one.
+    ///
+    fn visit_id(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) -> bool;
+
+    fn visit(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        if !self.id().is_empty() && !self.visit_id(field, kinds, visitor) {
+            self.id_mut().clear();
+        }
+    }
+
+    fn visit_required(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        if !self.id().is_empty() {
+            visitor.visit_required(field, kinds, self.id_mut());
+        }
+    }
+
+    fn read(&self, field: &str, kinds: IdKinds, reader: &mut impl IdReader) {
+        if !self.id().is_empty() {
+            reader.read(field, kinds, self.id());
+        }
+    }
+}
+
+impl IdField for String {
+    fn id(&self) -> &str {
+        self
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        self
+    }
+
+    fn visit_id(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) -> bool {
+        visitor.visit(field, kinds, self)
+    }
+}
+
+impl<const N: usize> IdField for FixedString<N> {
+    fn id(&self) -> &str {
+        self
+    }
+
+    fn id_mut(&mut self) -> &mut String {
+        self
+    }
+
+    fn visit_id(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) -> bool {
+        visitor.visit_limited(field, kinds, self, N)
+    }
+}
+
+#[ext]
+impl Option<String> {
+    fn visit(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        if let Some(id) = self.as_mut()
+            && !visitor.visit(field, kinds, id)
+        {
+            *self = None;
+        }
+    }
+
+    fn read(&self, field: &str, kinds: IdKinds, reader: &mut impl IdReader) {
+        if let Some(id) = self {
+            reader.read(field, kinds, id);
+        }
+    }
+}
+
+#[ext]
+impl<S> Vec<S>
+where
+    S: IdField,
+{
+    fn visit(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        self.retain_mut(|id| id.visit_id(field, kinds, visitor));
+    }
+
+    fn read(&self, field: &str, kinds: IdKinds, reader: &mut impl IdReader) {
+        for id in self {
+            reader.read(field, kinds, id.id());
+        }
+    }
+}
+
+#[ext]
+impl<S, T> Vec<(S, T)>
+where
+    S: IdField,
+{
+    fn visit(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        self.retain_mut(|(id, _)| id.visit_id(field, kinds, visitor));
+    }
+
+    fn read(&self, field: &str, kinds: IdKinds, reader: &mut impl IdReader) {
+        for (id, _) in self {
+            reader.read(field, kinds, id.id());
+        }
+    }
+}
+
+#[ext]
+impl<S, T> Vec<(T, S)>
+where
+    S: IdField,
+{
+    fn visit(&mut self, field: &str, kinds: IdKinds, visitor: &mut impl IdVisitor) {
+        self.retain_mut(|(_, id)| id.visit_id(field, kinds, visitor));
+    }
+
+    fn read(&self, field: &str, kinds: IdKinds, reader: &mut impl IdReader) {
+        for (_, id) in self {
+            reader.read(field, kinds, id.id());
+        }
+    }
+}
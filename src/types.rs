@@ -4,8 +4,17 @@ pub use cells::*;
 mod dialogue;
 pub use dialogue::*;
 
+mod filter;
+pub use filter::*;
+
+mod keys;
+pub use keys::*;
+
 mod objects;
 pub use objects::*;
 
 mod plugin;
 pub use plugin::*;
+
+mod report;
+pub use report::*;
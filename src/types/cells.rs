@@ -1,7 +1,18 @@
+use serde::Serialize;
 use tes3::esp::{Cell, EditorId, Landscape, ObjectInfo, PathGrid, Reference};
 
 use crate::prelude::*;
 
+/// The width of an exterior cell in world units.
+///
+pub const CELL_SIZE: f32 = 8192.0;
+
+/// The coordinates of the exterior cell that contains the given translation.
+///
+pub fn grid_coords([x, y, _]: [f32; 3]) -> (i32, i32) {
+    ((x / CELL_SIZE).floor() as i32, (y / CELL_SIZE).floor() as i32)
+}
+
 #[derive(Default)]
 pub struct Cells {
     pub interiors: HashMap<UString, Interior>,
@@ -38,6 +49,13 @@ impl Cells {
         self.exteriors.get_mut(&coords)
     }
 
+    pub fn get_cell(&self, key: &CellKey) -> Option<&Cell> {
+        match key {
+            CellKey::Exterior(coords) => self.get_exterior(*coords)?.cell.as_ref(),
+            CellKey::Interior(name) => self.get_interior(name)?.cell.as_ref(),
+        }
+    }
+
     pub fn get_or_create_exterior(&mut self, coords: (i32, i32)) -> &mut Exterior {
         self.exteriors.entry(coords).or_default()
     }
@@ -64,6 +82,17 @@ impl Cells {
         )
     }
 
+    pub fn iter_keyed(&self) -> impl Iterator<Item = (CellKey, &Cell)> {
+        Iterator::chain(
+            self.exteriors
+                .iter()
+                .filter_map(|(&coords, exterior)| Some((CellKey::Exterior(coords), exterior.cell.as_ref()?))),
+            self.interiors
+                .iter()
+                .filter_map(|(name, interior)| Some((CellKey::interior(name.as_str()), interior.cell.as_ref()?))),
+        )
+    }
+
     pub fn par_iter(&self) -> impl ParallelIterator<Item = &Cell> {
         ParallelIterator::chain(
             self.exteriors
@@ -88,57 +117,15 @@ impl Cells {
 }
 
 impl Cells {
-    /// Put 'moved references' into their the new cell's reference list.
-    ///
-    pub fn apply_moved_references(&mut self) {
-        let moved_references = self
-            .exteriors
-            .values_mut()
-            .filter_map(|exterior| exterior.cell.as_mut())
-            .flat_map(|cell| {
-                cell.references
-                    .extract_if(|_, r| r.mast_index == 0 && r.moved_cell.is_some())
-            })
-            .collect_vec();
-
-        for (key, mut reference) in moved_references {
-            let coords = reference.moved_cell.unwrap();
-            if let Some(exterior) = self.exteriors.get_mut(&coords)
-                && let Some(cell) = exterior.cell.as_mut()
-                && !cell.ignored()
-            {
-                info!(
-                    "Applying moved reference '{}' ({}) for cell {:?}",
-                    reference.id, key.1, coords
-                );
-                reference.moved_cell = None;
-                cell.references.insert(key, reference);
-            } else {
-                panic!(
-                    "Moved reference '{}' ({}) has invalid cell {:?}",
-                    reference.id, key.1, coords
-                );
-            }
-        }
-    }
-
     /// Remove all duplicate references.
     ///
     /// (i.e. those with identical id and transform)
     ///
-    pub fn remove_duplicate_references(&mut self) {
-        const MAX_ABS_DIFF: f32 = 1e-5;
-
-        let get_transform = |reference: &Reference| {
-            use glam::{Affine3A, EulerRot, Quat, Vec3};
-            let [x, y, z] = reference.rotation;
-            Affine3A::from_scale_rotation_translation(
-                Vec3::splat(reference.scale.unwrap_or(1.0)),
-                Quat::from_euler(EulerRot::XYZ, -x, -y, -z),
-                Vec3::from(reference.translation),
-            )
-        };
-
+    /// References of other masters are never removed, as removing them here would not remove
+    /// them from their masters. Of each set of duplicates, those references are kept first, then
+    /// the reference with the lowest reference index, which savegames are most likely to refer to.
+    ///
+    pub fn remove_duplicate_references(&mut self, options: &DuplicateReferences) {
         let mut reference_groups = HashMap::new();
 
         for cell in self.iter_mut() {
@@ -150,10 +137,18 @@ impl Cells {
                         .push((*key, get_transform(reference)));
                 }
             }
-            // For each group, remove references that have identical transforms.
+            // For each group, remove references that are duplicates of a preferred reference.
             for (_, mut group) in reference_groups.drain() {
+                group.sort_unstable_by_key(|(key, _)| std::cmp::Reverse(keep_order(*key)));
                 while let Some(a) = group.pop() {
-                    for (key, _) in group.extract_if(.., |b| a.1.abs_diff_eq(b.1, MAX_ABS_DIFF)) {
+                    let duplicates = group
+                        .extract_if(.., |b| {
+                            is_local(b.0)
+                                && a.1.abs_diff_eq(b.1, options.tolerance)
+                                && options.is_duplicate(&cell.references[&a.0], &cell.references[&b.0])
+                        })
+                        .collect_vec();
+                    for (key, _) in duplicates {
                         if let Some(reference) = cell.references.remove(&key) {
                             info!(
                                 "Removed duplicate reference: '{}' at {:?} from cell '{}'",
@@ -168,3 +163,221 @@ impl Cells {
         }
     }
 }
+
+/// Options for detecting duplicate references.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DuplicateReferences {
+    /// The maximum difference between the transforms of duplicates.
+    pub tolerance: f32,
+    /// Require the scales of duplicates to be within `tolerance` of each other.
+    pub compare_scale: bool,
+    /// References with different ownership or lock data are not duplicates.
+    pub compare_data: bool,
+    /// How to handle duplicates in neighboring exteriors, see `remove_border_duplicates`.
+    pub cross_border: CrossBorder,
+    /// The maximum distance from a cell's border for references to be compared across it.
+    pub border_distance: f32,
+}
+
+/// How to handle duplicate references that are in neighboring exteriors.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum CrossBorder {
+    /// Do not compare references across cell borders.
+    #[default]
+    Off,
+    /// Report duplicates, but do not remove them.
+    Report,
+    /// Remove duplicates.
+    Remove,
+}
+
+impl std::str::FromStr for CrossBorder {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" => Ok(Self::Off),
+            "report" => Ok(Self::Report),
+            "remove" => Ok(Self::Remove),
+            _ => bail!("Invalid cross border mode, expected off, report or remove: {s}"),
+        }
+    }
+}
+
+/// A reference that duplicates a reference in a neighboring exterior.
+///
+#[derive(Serialize)]
+pub struct BorderDuplicate {
+    pub id: String,
+    /// The reference that was kept.
+    pub original: RecordKey,
+    pub duplicate: RecordKey,
+    pub removed: bool,
+}
+
+impl DuplicateReferences {
+    pub const DEFAULT: Self = Self {
+        tolerance: 1e-5,
+        compare_scale: true,
+        compare_data: false,
+        cross_border: CrossBorder::Off,
+        border_distance: 128.0,
+    };
+
+    /// Compare the details of references whose transforms are already known to be equal.
+    ///
+    fn is_duplicate(&self, a: &Reference, b: &Reference) -> bool {
+        if self.compare_scale {
+            let scale = |reference: &Reference| reference.scale.unwrap_or(1.0);
+            if (scale(a) - scale(b)).abs() > self.tolerance {
+                return false;
+            }
+        }
+        if self.compare_data {
+            let data = |reference: &Reference| {
+                (
+                    reference.owner.clone(),
+                    reference.owner_global.clone(),
+                    reference.owner_faction.clone(),
+                    reference.owner_faction_rank,
+                    reference.key.clone(),
+                    reference.trap.clone(),
+                    reference.lock_level,
+                )
+            };
+            if data(a) != data(b) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Default for DuplicateReferences {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl PluginData {
+    /// Find references that duplicate references in neighboring exteriors.
+    ///
+    /// Only references within `border_distance` of their cell's border are compared. Like in
+    /// `remove_duplicate_references`, references of other masters are never removed.
+    ///
+    /// The `file_name` is the name of this plugin, used to resolve local references.
+    ///
+    pub fn remove_border_duplicates(&mut self, options: &DuplicateReferences, file_name: &str) -> Vec<BorderDuplicate> {
+        if options.cross_border == CrossBorder::Off {
+            return Vec::new();
+        }
+
+        let is_near_border = |reference: &Reference, (x, y): (i32, i32)| {
+            let local_x = reference.translation[0] - x as f32 * CELL_SIZE;
+            let local_y = reference.translation[1] - y as f32 * CELL_SIZE;
+            [local_x, local_y]
+                .iter()
+                .any(|&v| v < options.border_distance || v > CELL_SIZE - options.border_distance)
+        };
+
+        // Group references near borders by their id.
+        let mut reference_groups: HashMap<_, Vec<_>> = HashMap::new();
+        for (&coords, exterior) in &self.cells.exteriors {
+            let Some(cell) = &exterior.cell else { continue };
+            for (key, reference) in &cell.references {
+                if !reference.deleted() && reference.moved_cell.is_none() && is_near_border(reference, coords) {
+                    reference_groups
+                        .entry(reference.id.to_ascii_lowercase())
+                        .or_default()
+                        .push((coords, *key, get_transform(reference)));
+                }
+            }
+        }
+
+        let get_reference = |coords, key| {
+            let exterior = self.cells.get_exterior(coords)?;
+            exterior.cell.as_ref()?.references.get(&key)
+        };
+
+        let mut duplicates = Vec::new();
+
+        for (_, mut group) in reference_groups {
+            group.sort_unstable_by_key(|&(_, key, _)| keep_order(key));
+            let mut is_removed = vec![false; group.len()];
+            for (i, a) in group.iter().enumerate() {
+                if is_removed[i] {
+                    continue;
+                }
+                for (j, b) in group.iter().enumerate().skip(i + 1) {
+                    let is_neighbor = a.0 != b.0 && a.0.0.abs_diff(b.0.0) <= 1 && a.0.1.abs_diff(b.0.1) <= 1;
+                    if is_removed[j] || !is_local(b.1) || !is_neighbor || !a.2.abs_diff_eq(b.2, options.tolerance) {
+                        continue;
+                    }
+                    let (Some(ra), Some(rb)) = (get_reference(a.0, a.1), get_reference(b.0, b.1)) else {
+                        continue;
+                    };
+                    if options.is_duplicate(ra, rb) {
+                        is_removed[j] = true;
+                        duplicates.push((a.0, a.1, b.0, b.1, rb.id.clone()));
+                    }
+                }
+            }
+        }
+
+        let is_removed = options.cross_border == CrossBorder::Remove;
+
+        if is_removed {
+            for &(_, _, coords, key, _) in &duplicates {
+                if let Some(cell) = self.cells.get_exterior_mut(coords).and_then(|e| e.cell.as_mut())
+                    && let Some(reference) = cell.references.remove(&key)
+                {
+                    info!(
+                        "Removed duplicate reference: '{}' at {:?} from cell {:?}",
+                        reference.id, reference.translation, coords
+                    );
+                }
+            }
+        }
+
+        let reference_key = |coords, (mast_index, refr_index): (u32, u32)| RecordKey::Reference {
+            cell: CellKey::Exterior(coords),
+            owner: self.header.owner_name(mast_index, file_name),
+            index: refr_index,
+        };
+
+        duplicates
+            .into_iter()
+            .map(|(a_coords, a_key, b_coords, b_key, id)| BorderDuplicate {
+                id,
+                original: reference_key(a_coords, a_key),
+                duplicate: reference_key(b_coords, b_key),
+                removed: is_removed,
+            })
+            .collect()
+    }
+}
+
+/// The transform of a reference, excluding its scale, see `DuplicateReferences::compare_scale`.
+///
+fn get_transform(reference: &Reference) -> glam::Affine3A {
+    use glam::{Affine3A, EulerRot, Quat, Vec3};
+    let [x, y, z] = reference.rotation;
+    Affine3A::from_rotation_translation(
+        Quat::from_euler(EulerRot::XYZ, -x, -y, -z),
+        Vec3::from(reference.translation),
+    )
+}
+
+/// Whether the reference with the given key is defined by this plugin, rather than by a master.
+///
+fn is_local((mast_index, _): (u32, u32)) -> bool {
+    mast_index == 0
+}
+
+/// The order in which duplicates are kept, references of other masters first.
+///
+fn keep_order(key: (u32, u32)) -> (bool, (u32, u32)) {
+    (is_local(key), key)
+}
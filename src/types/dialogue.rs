@@ -56,6 +56,24 @@ impl DialogueGroup {
         self.infos.push_back(info);
     }
 
+    /// Removes the `DialogueInfo` with the specified `id`.
+    ///
+    /// The links of its neighbors are updated to skip over the removed `INFO`.
+    ///
+    pub fn remove_info(&mut self, id: &str) -> Option<DialogueInfo> {
+        let i = self.find(id)?;
+        let info = self.infos.remove(i)?;
+
+        if let Some(prev) = i.checked_sub(1).and_then(|j| self.infos.get_mut(j)) {
+            prev.next_id.clone_from(&info.next_id);
+        }
+        if let Some(next) = self.infos.get_mut(i) {
+            next.prev_id.clone_from(&info.prev_id);
+        }
+
+        Some(info)
+    }
+
     /// Repairs the `prev_id` and `next_id` links between `DialogueInfo` objects.
     ///
     /// Note: Both front/back links are left unmodified to match engine behavior.
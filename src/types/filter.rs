@@ -0,0 +1,335 @@
+use regex::{Regex, RegexBuilder};
+use tes3::esp::{EditorId, TES3Object, TypeInfo};
+
+use crate::prelude::*;
+
+/// Selects which contents of a plugin will be merged.
+///
+/// Content is merged if it matches the `include` rules and does not match the `exclude` rules.
+///
+/// Exteriors and interiors are filtered per record, so a landscape may be merged without its
+/// cell. Dialogue topics are filtered together with all of their infos.
+///
+/// Landscapes refer to textures by index, so the textures used by merged landscapes are always
+/// merged along with them.
+///
+#[derive(Default)]
+pub struct MergeFilter {
+    pub include: FilterRules,
+    pub exclude: FilterRules,
+}
+
+/// A set of rules for matching the contents of a plugin.
+///
+/// When including, content must match every non-empty list of rules that applies to it. \
+/// When excluding, content must match any non-empty list of rules that applies to it.
+///
+#[derive(Default)]
+pub struct FilterRules {
+    /// Record tags, such as `NPC_` or `LAND`.
+    pub tags: Vec<String>,
+    /// Patterns matching the ids of objects.
+    pub ids: Vec<Regex>,
+    /// Patterns matching the names of interior cells.
+    pub interiors: Vec<Regex>,
+    /// Rectangles of exterior cell coordinates.
+    pub exteriors: Vec<GridRect>,
+    /// Patterns matching the ids of dialogue topics.
+    pub topics: Vec<Regex>,
+}
+
+/// An inclusive rectangle of exterior cell coordinates.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct GridRect {
+    pub min: (i32, i32),
+    pub max: (i32, i32),
+}
+
+/// Describes the contents of a plugin for matching against filter rules.
+///
+#[derive(Clone, Copy)]
+enum Subject<'a> {
+    Object { tag: &'a str, id: &'a str },
+    Exterior { tag: &'a str, coords: (i32, i32) },
+    Interior { tag: &'a str, name: &'a str },
+    Topic { id: &'a str },
+}
+
+impl GridRect {
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.min.0..=self.max.0).contains(&x) && (self.min.1..=self.max.1).contains(&y)
+    }
+}
+
+impl FilterRules {
+    pub const NONE: Self = Self {
+        tags: Vec::new(),
+        ids: Vec::new(),
+        interiors: Vec::new(),
+        exteriors: Vec::new(),
+        topics: Vec::new(),
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+            && self.ids.is_empty()
+            && self.interiors.is_empty()
+            && self.exteriors.is_empty()
+            && self.topics.is_empty()
+    }
+
+    /// Match each non-empty list of rules that applies to the subject against it.
+    ///
+    /// Lists of rules that are not applicable to the subject are skipped.
+    ///
+    fn matches<'a>(&'a self, subject: Subject<'a>) -> impl Iterator<Item = bool> + 'a {
+        let tag = match subject {
+            Subject::Object { tag, .. } | Subject::Exterior { tag, .. } | Subject::Interior { tag, .. } => tag,
+            Subject::Topic { .. } => "DIAL",
+        };
+        let id = match subject {
+            Subject::Object { id, .. } => Some(id),
+            _ => None,
+        };
+        let name = match subject {
+            Subject::Interior { name, .. } => Some(name),
+            _ => None,
+        };
+        let coords = match subject {
+            Subject::Exterior { coords, .. } => Some(coords),
+            _ => None,
+        };
+        let topic = match subject {
+            Subject::Topic { id } => Some(id),
+            _ => None,
+        };
+        [
+            (!self.tags.is_empty()).then(|| {
+                // Dialogue topics are filtered together with their infos.
+                let is_dialogue = tag == "DIAL";
+                self.tags.iter().any(|t| {
+                    t.eq_ignore_ascii_case(tag) || (is_dialogue && t.eq_ignore_ascii_case("INFO"))
+                })
+            }),
+            id.filter(|_| !self.ids.is_empty())
+                .map(|id| self.ids.iter().any(|re| re.is_match(id))),
+            name.filter(|_| !self.interiors.is_empty())
+                .map(|name| self.interiors.iter().any(|re| re.is_match(name))),
+            coords
+                .filter(|_| !self.exteriors.is_empty())
+                .map(|coords| self.exteriors.iter().any(|rect| rect.contains(coords))),
+            topic
+                .filter(|_| !self.topics.is_empty())
+                .map(|topic| self.topics.iter().any(|re| re.is_match(topic))),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl MergeFilter {
+    pub const NONE: Self = Self {
+        include: FilterRules::NONE,
+        exclude: FilterRules::NONE,
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    fn is_merged(&self, subject: Subject<'_>) -> bool {
+        let included = self.include.matches(subject).all(|matched| matched);
+        let excluded = self.exclude.matches(subject).any(|matched| matched);
+        included && !excluded
+    }
+
+    /// Remove the contents of `plugin` that should not be merged.
+    ///
+    /// Returns the removed contents, which uses the same header as `plugin`. This also contains
+    /// copies of the merged textures that its landscapes use, so it can be saved as a plugin.
+    ///
+    pub fn split(&self, plugin: &mut PluginData) -> PluginData {
+        let mut excluded = PluginData::new();
+        excluded.header = plugin.header.clone();
+
+        excluded.objects = plugin
+            .objects
+            .extract_if(|_, object| {
+                !self.is_merged(Subject::Object {
+                    tag: object.tag_str(),
+                    id: &object.editor_id(),
+                })
+            })
+            .collect();
+
+        for (&coords, exterior) in &mut plugin.cells.exteriors {
+            let is_merged = |tag| self.is_merged(Subject::Exterior { tag, coords });
+            let target = excluded.cells.get_or_create_exterior(coords);
+            target.cell = exterior.cell.take_if(|_| !is_merged("CELL"));
+            target.landscape = exterior.landscape.take_if(|_| !is_merged("LAND"));
+            target.pathgrid = exterior.pathgrid.take_if(|_| !is_merged("PGRD"));
+        }
+
+        for (name, interior) in &mut plugin.cells.interiors {
+            let name = name.as_str();
+            let is_merged = |tag| self.is_merged(Subject::Interior { tag, name });
+            let target = excluded.cells.get_or_create_interior(name);
+            target.cell = interior.cell.take_if(|_| !is_merged("CELL"));
+            target.pathgrid = interior.pathgrid.take_if(|_| !is_merged("PGRD"));
+        }
+
+        excluded.dialogues = plugin
+            .dialogues
+            .extract_if(|id, _| !self.is_merged(Subject::Topic { id: id.as_str() }))
+            .collect();
+
+        let used_textures = plugin.used_texture_indices();
+        let used_excluded_textures = excluded
+            .objects
+            .extract_if(|_, object| match object {
+                TES3Object::LandscapeTexture(texture) => {
+                    landscape_index(texture).is_some_and(|index| used_textures.contains(&index))
+                }
+                _ => false,
+            })
+            .collect_vec();
+        plugin.objects.extend(used_excluded_textures);
+
+        let used_textures = excluded.used_texture_indices();
+        for (key, object) in &plugin.objects {
+            if let TES3Object::LandscapeTexture(texture) = object
+                && landscape_index(texture).is_some_and(|index| used_textures.contains(&index))
+            {
+                excluded.objects.insert(key.clone(), object.clone());
+            }
+        }
+
+        plugin.cells.exteriors.retain(|_, exterior| exterior.count_objects() != 0);
+        plugin.cells.interiors.retain(|_, interior| interior.count_objects() != 0);
+        excluded.cells.exteriors.retain(|_, exterior| exterior.count_objects() != 0);
+        excluded.cells.interiors.retain(|_, interior| interior.count_objects() != 0);
+
+        excluded
+    }
+}
+
+/// Build a case-insensitive pattern from a glob, or from a regex if enclosed in slashes.
+///
+/// (e.g. `"ebony_*"` or `"/^ebony_(cuirass|helm)$/"`)
+///
+pub fn id_pattern(pattern: &str) -> Result<Regex> {
+    let source = match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+        Some(source) => source.to_owned(),
+        None => {
+            let mut source = String::from("^");
+            for c in pattern.chars() {
+                match c {
+                    '*' => source.push_str(".*"),
+                    '?' => source.push('.'),
+                    _ => source.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+                }
+            }
+            source.push('$');
+            source
+        }
+    };
+    RegexBuilder::new(&source)
+        .case_insensitive(true)
+        .build()
+        .with_context(|| format!("Invalid pattern: {pattern}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob_pattern() {
+        let pattern = id_pattern("ebony_*").unwrap();
+        assert!(pattern.is_match("ebony_cuirass"));
+        assert!(pattern.is_match("Ebony_Helm"));
+        assert!(!pattern.is_match("glass_ebony_helm"));
+
+        let pattern = id_pattern("a?c.nif").unwrap();
+        assert!(pattern.is_match("abc.nif"));
+        assert!(!pattern.is_match("abcxnif"));
+    }
+
+    #[test]
+    fn regex_pattern() {
+        let pattern = id_pattern("/^ebony_(cuirass|helm)$/").unwrap();
+        assert!(pattern.is_match("EBONY_HELM"));
+        assert!(!pattern.is_match("ebony_boots"));
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = MergeFilter {
+            include: FilterRules {
+                tags: vec!["LAND".into(), "PGRD".into()],
+                ..FilterRules::NONE
+            },
+            exclude: FilterRules {
+                exteriors: vec![GridRect { min: (0, 0), max: (1, 1) }],
+                ..FilterRules::NONE
+            },
+        };
+        let exterior = |tag, coords| Subject::Exterior { tag, coords };
+        assert!(filter.is_merged(exterior("LAND", (2, 2))));
+        assert!(!filter.is_merged(exterior("LAND", (1, 1))));
+        assert!(!filter.is_merged(exterior("CELL", (2, 2))));
+        assert!(!filter.is_merged(Subject::Object { tag: "NPC_", id: "fargoth" }));
+    }
+
+    #[test]
+    fn include_ids_only_applies_to_objects() {
+        let filter = MergeFilter {
+            include: FilterRules {
+                ids: vec![id_pattern("foo_*").unwrap()],
+                ..FilterRules::NONE
+            },
+            exclude: FilterRules::NONE,
+        };
+        assert!(filter.is_merged(Subject::Object { tag: "MISC", id: "foo_bar" }));
+        assert!(!filter.is_merged(Subject::Object { tag: "MISC", id: "bar" }));
+        assert!(filter.is_merged(Subject::Exterior { tag: "CELL", coords: (0, 0) }));
+        assert!(filter.is_merged(Subject::Interior { tag: "CELL", name: "Balmora" }));
+        assert!(filter.is_merged(Subject::Topic { id: "background" }));
+    }
+
+    #[test]
+    fn include_exteriors_and_topics() {
+        let filter = MergeFilter {
+            include: FilterRules {
+                exteriors: vec![GridRect { min: (0, 0), max: (1, 1) }],
+                topics: vec![id_pattern("back*").unwrap()],
+                ..FilterRules::NONE
+            },
+            exclude: FilterRules::NONE,
+        };
+        assert!(filter.is_merged(Subject::Exterior { tag: "LAND", coords: (1, 0) }));
+        assert!(!filter.is_merged(Subject::Exterior { tag: "LAND", coords: (2, 0) }));
+        assert!(filter.is_merged(Subject::Topic { id: "background" }));
+        assert!(!filter.is_merged(Subject::Topic { id: "little secret" }));
+        assert!(filter.is_merged(Subject::Interior { tag: "CELL", name: "Balmora" }));
+        assert!(filter.is_merged(Subject::Object { tag: "NPC_", id: "fargoth" }));
+    }
+
+    #[test]
+    fn exclude_ids_and_interiors() {
+        let filter = MergeFilter {
+            include: FilterRules::NONE,
+            exclude: FilterRules {
+                ids: vec![id_pattern("foo_*").unwrap()],
+                interiors: vec![id_pattern("balmora*").unwrap()],
+                ..FilterRules::NONE
+            },
+        };
+        assert!(!filter.is_merged(Subject::Object { tag: "MISC", id: "foo_bar" }));
+        assert!(filter.is_merged(Subject::Object { tag: "MISC", id: "bar" }));
+        assert!(!filter.is_merged(Subject::Interior { tag: "CELL", name: "Balmora, Guild" }));
+        assert!(filter.is_merged(Subject::Interior { tag: "CELL", name: "Caldera" }));
+        assert!(filter.is_merged(Subject::Exterior { tag: "CELL", coords: (0, 0) }));
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use tes3::esp::*;
+
+use crate::prelude::*;
+
+/// Identifies a cell independently of the file it was loaded from.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CellKey {
+    Exterior((i32, i32)),
+    Interior(ObjectId), // Note: Always lowercase.
+}
+
+/// Identifies a record independently of the file it was loaded from.
+///
+/// These mirror the keys used by `PluginData`, with the exception of references which
+/// use the owning file's name rather than an index into some file's masters list.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RecordKey {
+    Object(#[serde(with = "tag_serde")] TaggedId),
+    Cell(CellKey),
+    Landscape((i32, i32)),
+    PathGrid(CellKey),
+    Reference {
+        cell: CellKey,
+        owner: ObjectId, // Note: Always lowercase.
+        index: u32,
+    },
+    Dialogue(ObjectId),
+    Info {
+        dialogue: ObjectId,
+        id: String,
+    },
+}
+
+impl CellKey {
+    pub fn interior(name: &str) -> Self {
+        Self::Interior(name.to_ascii_lowercase())
+    }
+}
+
+impl fmt::Display for CellKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exterior(coords) => write!(f, "{coords:?}"),
+            Self::Interior(name) => write!(f, "'{name}'"),
+        }
+    }
+}
+
+impl fmt::Display for RecordKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Object((tag, id)) => match std::str::from_utf8(*tag) {
+                Ok(tag) if tag != "\0\0\0\0" => write!(f, "{tag} {id}"),
+                _ => write!(f, "OBJ {id}"),
+            },
+            Self::Cell(cell) => write!(f, "CELL {cell}"),
+            Self::Landscape(coords) => write!(f, "LAND {coords:?}"),
+            Self::PathGrid(cell) => write!(f, "PGRD {cell}"),
+            Self::Reference { cell, owner, index } => write!(f, "REFR {cell} ({owner} #{index})"),
+            Self::Dialogue(id) => write!(f, "DIAL {id}"),
+            Self::Info { dialogue, id } => write!(f, "INFO {dialogue} ({id})"),
+        }
+    }
+}
+
+impl PluginData {
+    /// Collect the keys of all records contained in this plugin.
+    ///
+    /// The `file_name` is the name of this plugin, used to resolve local references.
+    ///
+    pub fn record_keys(&self, file_name: &str) -> Vec<RecordKey> {
+        let mut keys = Vec::with_capacity(self.count_objects());
+
+        keys.extend(self.objects.keys().cloned().map(RecordKey::Object));
+
+        for (&coords, exterior) in &self.cells.exteriors {
+            let cell_key = CellKey::Exterior(coords);
+            if let Some(cell) = &exterior.cell {
+                keys.push(RecordKey::Cell(cell_key.clone()));
+                keys.extend(self.reference_keys(cell, &cell_key, file_name));
+            }
+            if exterior.landscape.is_some() {
+                keys.push(RecordKey::Landscape(coords));
+            }
+            if exterior.pathgrid.is_some() {
+                keys.push(RecordKey::PathGrid(cell_key));
+            }
+        }
+
+        for (name, interior) in &self.cells.interiors {
+            let cell_key = CellKey::interior(name.as_str());
+            if let Some(cell) = &interior.cell {
+                keys.push(RecordKey::Cell(cell_key.clone()));
+                keys.extend(self.reference_keys(cell, &cell_key, file_name));
+            }
+            if interior.pathgrid.is_some() {
+                keys.push(RecordKey::PathGrid(cell_key));
+            }
+        }
+
+        for (dialogue_id, group) in &self.dialogues {
+            keys.push(RecordKey::Dialogue(dialogue_id.clone()));
+            keys.extend(group.infos.iter().map(|info| RecordKey::Info {
+                dialogue: dialogue_id.clone(),
+                id: info.id.clone(),
+            }));
+        }
+
+        keys
+    }
+
+    fn reference_keys<'a>(
+        &'a self,
+        cell: &'a Cell,
+        cell_key: &'a CellKey,
+        file_name: &'a str,
+    ) -> impl Iterator<Item = RecordKey> + 'a {
+        cell.references.keys().map(move |&(mast_index, refr_index)| RecordKey::Reference {
+            cell: cell_key.clone(),
+            owner: self.header.owner_name(mast_index, file_name),
+            index: refr_index,
+        })
+    }
+}
+
+#[ext(HeaderOwnerExt)]
+impl Header {
+    /// The lowercase name of the file that owns references of the given master index.
+    ///
+    pub(crate) fn owner_name(&self, mast_index: u32, file_name: &str) -> ObjectId {
+        let name = match mast_index.checked_sub(1) {
+            Some(i) => self.masters.get(i as usize).map_or(file_name, |(name, _)| name.as_str()),
+            None => file_name,
+        };
+        name.to_ascii_lowercase()
+    }
+}
+
+/// (De)serialize the static tag of a `TaggedId` as a string.
+///
+mod tag_serde {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use tes3::esp::*;
+
+    use crate::{ObjectId, TaggedId};
+
+    const PHYSICAL: &[u8; 4] = &[0; 4];
+
+    const TAGS: &[&[u8; 4]] = &[
+        PHYSICAL,
+        Birthsign::TAG,
+        Class::TAG,
+        Faction::TAG,
+        GameSetting::TAG,
+        GlobalVariable::TAG,
+        LandscapeTexture::TAG,
+        MagicEffect::TAG,
+        Race::TAG,
+        Region::TAG,
+        Script::TAG,
+        Skill::TAG,
+        Sound::TAG,
+        SoundGen::TAG,
+        StartScript::TAG,
+    ];
+
+    pub fn serialize<S: Serializer>((tag, id): &TaggedId, serializer: S) -> Result<S::Ok, S::Error> {
+        let tag = if *tag == PHYSICAL { "" } else { std::str::from_utf8(*tag).unwrap_or_default() };
+        serializer.collect_seq([tag, id.as_str()])
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TaggedId, D::Error> {
+        let (tag, id) = <(String, ObjectId)>::deserialize(deserializer)?;
+        let tag = if tag.is_empty() { PHYSICAL.as_slice() } else { tag.as_bytes() };
+        TAGS.iter()
+            .find(|known| known.as_slice() == tag)
+            .map(|&known| (known, id))
+            .ok_or_else(|| D::Error::custom("unknown record tag"))
+    }
+}
@@ -0,0 +1,135 @@
+use std::fs::File;
+use std::io::BufWriter;
+
+use serde::Serialize;
+
+use crate::prelude::*;
+
+/// Details about a merge that are worth reviewing before the result is used.
+///
+#[derive(Default, Serialize)]
+pub struct MergeReport {
+    pub conflicts: Vec<Conflict>,
+    pub later_master_dependencies: Vec<LaterMasterDependency>,
+    /// Records of the plugin that were excluded from the merge by filters.
+    pub excluded: Vec<RecordKey>,
+    pub moved_references: Vec<MovedReference>,
+    pub relocations: Vec<Relocation>,
+    pub border_duplicates: Vec<BorderDuplicate>,
+    /// Records that referred to deleted objects, see `MergeOptions::remove_deleted`.
+    pub cleaned: CleanReport,
+    pub replaced_ids: Vec<ReplacedId>,
+    /// Fields that were redirected to renamed interiors.
+    pub renamed_interiors: Vec<ReplacedId>,
+    pub duplicate_textures: Vec<DuplicateTexture>,
+    /// Records that were removed by `MergeOptions::remove_unused`.
+    pub unused: Vec<RecordKey>,
+    /// The number of landscape texture indices used by the master, see `TEXTURE_INDEX_LIMIT`.
+    pub texture_indices: u32,
+}
+
+/// A record of the plugin that is also defined by other masters of the plugin.
+///
+#[derive(Serialize)]
+pub struct Conflict {
+    pub key: RecordKey,
+    /// The masters that define this record, in load order.
+    pub masters: Vec<String>,
+    /// The record was last defined by a master other than the merge target.
+    ///
+    /// The plugin's changes to such records will no longer be applied once merged if that
+    /// master is loaded after the merge target.
+    pub overrides_other_master: bool,
+}
+
+impl MergeReport {
+    /// Write the contents of the report to the log.
+    ///
+    pub fn log(&self) {
+        for conflict in &self.conflicts {
+            if conflict.overrides_other_master {
+                warn!("Conflict: {} overrides a record of {}", conflict.key, conflict.masters.join(", "));
+            } else {
+                info!("Conflict: {} is also defined by {}", conflict.key, conflict.masters.join(", "));
+            }
+        }
+        for key in &self.excluded {
+            info!("Excluded from merge: {key}");
+        }
+        for moved in &self.moved_references {
+            if moved.is_valid() {
+                info!("Moved reference: {} '{}' to {:?} ({:?})", moved.key, moved.id, moved.destination, moved.result);
+            } else {
+                warn!(
+                    "Moved reference: {} '{}' to {:?} is located in {:?} ({:?})",
+                    moved.key, moved.id, moved.destination, moved.expected, moved.result
+                );
+            }
+        }
+        for relocation in &self.relocations {
+            info!(
+                "Relocated reference: {} '{}' from {:?} to {:?}",
+                relocation.key, relocation.id, relocation.from, relocation.to
+            );
+        }
+        for key in &self.cleaned.deleted {
+            info!("Deleted: {key}");
+        }
+        for key in &self.cleaned.bodyparts {
+            info!("Body part of deleted race: {key}");
+        }
+        for key in &self.cleaned.sound_gens {
+            info!("Sound gen of deleted creature: {key}");
+        }
+        for field in &self.cleaned.fields {
+            info!("Cleaned: {field}");
+        }
+        for field in &self.cleaned.fallbacks {
+            info!("Fallback: {field}");
+        }
+        for textures in &self.cleaned.textures {
+            info!("Cleaned: {textures}");
+        }
+        for reference in &self.cleaned.safe_deleted {
+            info!(
+                "Safe deleted: '{}' ({}, {}) in '{}'",
+                reference.id, reference.mast_index, reference.refr_index, reference.cell
+            );
+        }
+        let texture_usage = f64::from(self.texture_indices) / f64::from(TEXTURE_INDEX_LIMIT);
+        if texture_usage >= 0.9 {
+            warn!(
+                "Landscape texture indices: {} of {TEXTURE_INDEX_LIMIT} in use ({:.1}%)",
+                self.texture_indices,
+                texture_usage * 100.0
+            );
+        } else if self.texture_indices != 0 {
+            info!(
+                "Landscape texture indices: {} of {TEXTURE_INDEX_LIMIT} in use ({:.1}%)",
+                self.texture_indices,
+                texture_usage * 100.0
+            );
+        }
+        for duplicate in &self.border_duplicates {
+            warn!(
+                "Duplicate reference: {} '{}' duplicates {}{}",
+                duplicate.duplicate,
+                duplicate.id,
+                duplicate.original,
+                if duplicate.removed { " (removed)" } else { "" }
+            );
+        }
+    }
+
+    pub fn save_path(&self, path: &Path) -> Result<()> {
+        save_json(self, path)
+    }
+}
+
+/// Serialize `value` into a JSON file at `path`.
+///
+pub fn save_json<T: Serialize>(value: &T, path: &Path) -> Result<()> {
+    let file = File::create(path).with_context(|| path.display().to_string())?;
+    serde_json::to_writer_pretty(BufWriter::new(file), value) //
+        .with_context(|| path.display().to_string())
+}
@@ -0,0 +1,135 @@
+use serde::Serialize;
+use tes3::esp::{Header, Reference};
+
+use crate::prelude::*;
+
+/// Lists the records that refer to each id, and the fields through which they do so.
+///
+#[derive(Default)]
+pub struct UsageIndex {
+    uses: HashMap<UString, Vec<Usage>>,
+}
+
+/// A field of a record that refers to an id.
+///
+#[derive(Clone, Debug, Serialize)]
+pub struct Usage {
+    pub key: RecordKey,
+    /// Describes the record. (e.g. `NPC_ fargoth`)
+    pub record: String,
+    pub field: String,
+}
+
+impl UsageIndex {
+    /// The uses of the given id, which is case-insensitive.
+    ///
+    pub fn get(&self, id: &str) -> &[Usage] {
+        self.uses.get(id.as_uncased()).map_or(&[], Vec::as_slice)
+    }
+
+    /// The number of distinct ids that are used.
+    ///
+    pub fn len(&self) -> usize {
+        self.uses.len()
+    }
+}
+
+impl PluginData {
+    /// Build an index of every record, cell reference and `INFO` that refers to another object.
+    ///
+    /// The `file_name` is the name of this plugin, used to resolve local references.
+    ///
+    pub fn usage_index(&self, file_name: &str) -> UsageIndex {
+        let mut index = UsageIndex::default();
+        for (id, usage) in self.uses(file_name) {
+            index.uses.entry(id.into()).or_default().push(usage);
+        }
+        index
+    }
+
+    /// Collect every id that is used by a record of this plugin, along with its use.
+    ///
+    pub(crate) fn uses(&self, file_name: &str) -> Vec<(String, Usage)> {
+        let mut collector = UsageCollector {
+            header: &self.header,
+            file_name,
+            cell: None,
+            base: String::new(),
+            key: RecordKey::Dialogue(String::new()),
+            record: String::new(),
+            uses: Vec::new(),
+        };
+
+        for (key, object) in &self.objects {
+            let record = format!("{} {}", object.tag_str(), object.editor_id());
+            collector.set_record(RecordKey::Object(key.clone()), record);
+            object.read_ids(&mut collector);
+        }
+
+        for (cell_key, cell) in self.cells.iter_keyed() {
+            collector.cell = Some(cell_key.clone());
+            collector.set_record(RecordKey::Cell(cell_key), format!("CELL '{}'", cell.editor_id()));
+            cell.read_ids(&mut collector);
+        }
+
+        for (topic, group) in &self.dialogues {
+            for info in &group.infos {
+                let key = RecordKey::Info {
+                    dialogue: topic.clone(),
+                    id: info.id.clone(),
+                };
+                collector.set_record(key, format!("INFO {topic} ({})", info.id));
+                info.read_ids(&mut collector);
+            }
+        }
+
+        collector.uses
+    }
+}
+
+struct UsageCollector<'a> {
+    header: &'a Header,
+    file_name: &'a str,
+    /// The cell being visited, if any.
+    cell: Option<CellKey>,
+    /// Describes the record being visited.
+    base: String,
+    /// The record or reference whose fields are being visited.
+    key: RecordKey,
+    /// Describes the record or reference whose fields are being visited.
+    record: String,
+    uses: Vec<(String, Usage)>,
+}
+
+impl UsageCollector<'_> {
+    fn set_record(&mut self, key: RecordKey, record: String) {
+        self.key = key;
+        self.base.clone_from(&record);
+        self.record = record;
+    }
+}
+
+impl IdReader for UsageCollector<'_> {
+    fn read(&mut self, field: &str, _: IdKinds, id: &str) {
+        self.uses.push((
+            id.to_owned(),
+            Usage {
+                key: self.key.clone(),
+                record: self.record.clone(),
+                field: field.to_owned(),
+            },
+        ));
+    }
+
+    fn read_reference(&mut self, (mast_index, refr_index): (u32, u32), reference: &Reference) {
+        if let Some(cell) = &self.cell {
+            self.key = RecordKey::Reference {
+                cell: cell.clone(),
+                owner: self.header.owner_name(mast_index, self.file_name),
+                index: refr_index,
+            };
+        }
+        self.record = format!("{} REFR '{}' {:?}", self.base, reference.id, (mast_index, refr_index));
+        self.read("id", IdKinds::PHYSICAL, &reference.id);
+    }
+}
@@ -0,0 +1,646 @@
+#!/usr/bin/env python3
+"""Generate the small plugins used as fixtures by the integration tests.
+
+Each fixture is a directory of plugins built from scratch, so the scenario a test covers can
+be read here rather than reverse engineered from binary files. Run from any directory:
+
+    python tests/assets/generate_fixtures.py [FIXTURE ...]
+
+Without arguments every fixture is regenerated.
+"""
+
+import json
+import struct
+import sys
+from pathlib import Path
+
+ASSETS = Path(__file__).parent
+
+FIXTURES = {}
+
+DELETED = 0x20
+
+
+def fixture(function):
+    FIXTURES[function.__name__] = function
+    return function
+
+
+# ---------------------------------------------------------------------------
+# Encoding
+
+
+def u8(value):
+    return struct.pack("<B", value)
+
+
+def u32(value):
+    return struct.pack("<I", value)
+
+
+def i32s(*values):
+    return struct.pack(f"<{len(values)}i", *values)
+
+
+def f32s(*values):
+    return struct.pack(f"<{len(values)}f", *values)
+
+
+def zstring(text):
+    return text.encode("latin-1") + b"\0"
+
+
+def fixed(text, size):
+    return text.encode("latin-1").ljust(size, b"\0")
+
+
+def sub(tag, data):
+    return tag.encode() + u32(len(data)) + data
+
+
+def record(tag, *subrecords, deleted=False, flags=0):
+    data = b"".join(subrecords)
+    if deleted:
+        data += sub("DELE", u32(0))
+        flags |= DELETED
+    return tag.encode() + struct.pack("<III", len(data), 0, flags) + data
+
+
+def save(path, records, masters=(), esm=False):
+    """Write a plugin, reading the sizes of its masters from the same directory."""
+    hedr = struct.pack("<fI32s256sI", 1.3, int(esm), b"", b"", len(records))
+    header = [sub("HEDR", hedr)]
+    for name in masters:
+        size = (path.parent / name).stat().st_size
+        header += [sub("MAST", zstring(name)), sub("DATA", struct.pack("<Q", size))]
+    path.parent.mkdir(parents=True, exist_ok=True)
+    path.write_bytes(record("TES3", *header) + b"".join(records))
+
+
+# ---------------------------------------------------------------------------
+# Objects
+
+
+def misc(id, deleted=False):
+    return record(
+        "MISC",
+        sub("NAME", zstring(id)),
+        sub("MODL", zstring("m\\misc.nif")),
+        sub("MCDT", f32s(1.0) + u32(1) + u32(0)),
+        deleted=deleted,
+    )
+
+
+def door(id):
+    return record("DOOR", sub("NAME", zstring(id)), sub("MODL", zstring("d\\door.nif")))
+
+
+def spell(id, deleted=False):
+    return record("SPEL", sub("NAME", zstring(id)), sub("SPDT", bytes(12)), deleted=deleted)
+
+
+def race(id, deleted=False):
+    skills = bytes.fromhex("ffffffff00000000") * 7
+    attributes = bytes.fromhex("32000000") * 16
+    data = skills + attributes + f32s(1.0, 1.0, 1.0, 1.0) + u32(0)
+    return record("RACE", sub("NAME", zstring(id)), sub("RADT", data), deleted=deleted)
+
+
+def class_(id, deleted=False):
+    data = bytes.fromhex(
+        "0000000001000000000000000500000000000000060000000100000007000000"
+        "02000000080000000300000009000000040000000000000000000000"
+    )
+    return record("CLAS", sub("NAME", zstring(id)), sub("CLDT", data), deleted=deleted)
+
+
+def bodypart(id, race, part_type=0):
+    """A body part of the race, where `part_type` is 0 for skin, 1 for clothing and 2 for armor."""
+    return record(
+        "BODY",
+        sub("NAME", zstring(id)),
+        sub("MODL", zstring("b\\part.nif")),
+        sub("FNAM", zstring(race)),
+        sub("BYDT", bytes([3, 0, 0, part_type])),
+    )
+
+
+def enchantment(id):
+    return record("ENCH", sub("NAME", zstring(id)), sub("ENDT", u32(3) + bytes(12)))
+
+
+def armor(id, bodypart, enchantment=None):
+    subrecords = [
+        sub("NAME", zstring(id)),
+        sub("MODL", zstring("a\\armor.nif")),
+        sub("AODT", bytes.fromhex("050000000000803f01000000640000006400000001000000")),
+        sub("INDX", u8(3)),
+        sub("BNAM", zstring(bodypart)),
+    ]
+    if enchantment is not None:
+        subrecords.append(sub("ENAM", zstring(enchantment)))
+    return record("ARMO", *subrecords)
+
+
+def creature(id, deleted=False):
+    data = u32(0) + u32(1) + u32(50) * 15 + (u32(1) + u32(5)) * 3 + u32(0)
+    return record(
+        "CREA",
+        sub("NAME", zstring(id)),
+        sub("MODL", zstring("r\\creature.nif")),
+        sub("NPDT", data),
+        sub("FLAG", u32(0x48)),
+        sub("AIDT", bytes.fromhex("00005a140000000000000000")),
+        deleted=deleted,
+    )
+
+
+def sound_gen(id, creature):
+    return record(
+        "SNDG",
+        sub("NAME", zstring(id)),
+        sub("DATA", u32(7)),
+        sub("CNAM", zstring(creature)),
+        sub("SNAM", zstring("Sound")),
+    )
+
+
+def npc(id, race="Race", class_="Class", inventory=(), destinations=(), packages=(), deleted=False):
+    """An NPC with autocalculated stats, carrying `(count, item)` and traveling to `(cell, xyz)`.
+
+    The `packages` are escort or follow packages as `(tag, target, cell)`, e.g. `("AI_E", "guard", "Bar")`.
+    """
+    subrecords = [sub("NAME", zstring(id)), sub("RNAM", zstring(race))]
+    if class_:
+        subrecords.append(sub("CNAM", zstring(class_)))
+    subrecords += [
+        sub("NPDT", bytes.fromhex("010032020000000000000000")),
+        sub("FLAG", u32(0x18)),
+    ]
+    for count, item in inventory:
+        subrecords.append(sub("NPCO", i32s(count) + fixed(item, 32)))
+    subrecords.append(sub("AIDT", bytes.fromhex("1e001e1e0000000000000000")))
+    for cell, translation in destinations:
+        subrecords.append(sub("DODT", f32s(*translation, 0, 0, 0)))
+        if cell:
+            subrecords.append(sub("DNAM", zstring(cell)))
+    for tag, target, cell in packages:
+        subrecords.append(sub(tag, f32s(0, 0, 0) + struct.pack("<H", 24) + fixed(target, 32) + struct.pack("<H", 1)))
+        subrecords.append(sub("CNDT", zstring(cell)))
+    return record("NPC_", *subrecords, deleted=deleted)
+
+
+def texture(id, index, file_name=None, deleted=False):
+    return record(
+        "LTEX",
+        sub("NAME", zstring(id)),
+        sub("INTV", u32(index)),
+        sub("DATA", zstring(file_name or f"{id}.dds")),
+        deleted=deleted,
+    )
+
+
+# ---------------------------------------------------------------------------
+# Dialogue
+
+
+def dialogue(id, deleted=False):
+    return record("DIAL", sub("NAME", zstring(id)), sub("DATA", u8(0)), deleted=deleted)
+
+
+def info(id, prev_id="", next_id="", text="", speaker=None, cell=None, not_cells=()):
+    """A dialogue response, optionally limited to a speaker in `cell` and not in any of `not_cells`."""
+    subrecords = [
+        sub("INAM", zstring(id)),
+        sub("PNAM", zstring(prev_id)),
+        sub("NNAM", zstring(next_id)),
+        sub("DATA", bytes.fromhex("0000000000000000ffffff00")),
+    ]
+    if speaker is not None:
+        subrecords.append(sub("ONAM", zstring(speaker)))
+    if cell is not None:
+        subrecords.append(sub("ANAM", zstring(cell)))
+    subrecords.append(sub("NAME", text.encode("latin-1")))
+    for slot, not_cell in enumerate(not_cells):
+        # Slot, type `B` (not cell), function `LX` and comparison `0` (equal), followed by the name.
+        subrecords.append(sub("SCVR", f"{slot}BLX0{not_cell}".encode("latin-1")))
+        subrecords.append(sub("INTV", i32s(0)))
+    return record("INFO", *subrecords)
+
+
+# ---------------------------------------------------------------------------
+# Cells
+
+
+CELL_SIZE = 8192
+
+
+def landscape(grid, textures=()):
+    """A flat landscape, whose texture indices are filled in order from `(index, count)` pairs.
+
+    Indices are stored as they are in the file, so `0` is the default texture and `n` is the
+    texture whose `INTV` is `n - 1`.
+    """
+    indices = [index for index, count in textures for _ in range(count)]
+    indices += [0] * (256 - len(indices))
+    return record(
+        "LAND",
+        sub("INTV", i32s(*grid)),
+        sub("DATA", u32(0x0F)),
+        sub("VNML", bytes([0, 0, 127]) * 65 * 65),
+        sub("VHGT", f32s(0) + bytes(65 * 65 + 3)),
+        sub("WNAM", bytes(81)),
+        sub("VCLR", bytes([255]) * 65 * 65 * 3),
+        sub("VTEX", struct.pack("<256H", *indices)),
+    )
+
+
+def reference(
+    refr_index,
+    id,
+    translation=(0, 0, 0),
+    mast_index=0,
+    scale=None,
+    owner=None,
+    key=None,
+    trap=None,
+    destination=None,
+    moved_cell=None,
+    deleted=False,
+):
+    """A reference as `(mast_index, subrecords)`, where `destination` is a `(cell, xyz)` pair."""
+    frmr = u32(mast_index << 24 | refr_index)
+    subrecords = []
+    if moved_cell is not None:
+        subrecords += [sub("MVRF", frmr), sub("CNDT", i32s(*moved_cell))]
+    subrecords += [sub("FRMR", frmr), sub("NAME", zstring(id))]
+    if deleted:
+        return mast_index, subrecords + [sub("DELE", u32(0))]
+    if scale is not None:
+        subrecords.append(sub("XSCL", f32s(scale)))
+    if owner is not None:
+        subrecords.append(sub("ANAM", zstring(owner)))
+    if destination is not None:
+        cell, xyz = destination
+        subrecords.append(sub("DODT", f32s(*xyz, 0, 0, 0)))
+        if cell:
+            subrecords.append(sub("DNAM", zstring(cell)))
+    if key is not None:
+        subrecords.append(sub("KNAM", zstring(key)))
+    if trap is not None:
+        subrecords.append(sub("TNAM", zstring(trap)))
+    subrecords.append(sub("DATA", f32s(*translation, 0, 0, 0)))
+    return mast_index, subrecords
+
+
+def cell_references(references):
+    """The references of a cell, with those of other masters before the local ones."""
+    masters = [subrecords for mast_index, subrecords in references if mast_index != 0]
+    local = [subrecords for mast_index, subrecords in references if mast_index == 0]
+    result = [s for subrecords in masters for s in subrecords]
+    if local:
+        result.append(sub("NAM0", u32(len(local))))
+        result += [s for subrecords in local for s in subrecords]
+    return result
+
+
+def interior(name, references=(), deleted=False):
+    data = sub("DATA", u32(0x01) + i32s(0) + f32s(1.0))
+    if deleted:
+        # Unlike other records, the deletion marker of cells precedes their data.
+        return record("CELL", sub("NAME", zstring(name)), sub("DELE", u32(0)), data, flags=DELETED)
+    return record("CELL", sub("NAME", zstring(name)), data, *cell_references(references))
+
+
+def pathgrid(name):
+    """An empty path grid of the interior `name`."""
+    return record("PGRD", sub("DATA", i32s(0, 0) + struct.pack("<HH", 512, 0)), sub("NAME", zstring(name)))
+
+
+def exterior(grid, references=()):
+    data = sub("DATA", u32(0x02) + i32s(*grid))
+    return record("CELL", sub("NAME", zstring("")), data, *cell_references(references))
+
+
+def position(grid, offset=(4096, 4096, 0)):
+    """A translation within the exterior cell at `grid`."""
+    return (grid[0] * CELL_SIZE + offset[0], grid[1] * CELL_SIZE + offset[1], offset[2])
+
+
+# ---------------------------------------------------------------------------
+# Fixtures
+
+
+@fixture
+def conflicts():
+    """A plugin edits an item overridden by the merge target, and an item of another master."""
+    root = ASSETS / "conflicts"
+    save(root / "Other.esm", [misc("shared"), misc("other_only")], esm=True)
+    save(root / "Master.esm", [misc("shared"), misc("master_only")], ["Other.esm"], esm=True)
+    save(root / "Plugin.esp", [misc("shared"), misc("other_only")], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def flatten_masters():
+    """A chain of masters, where the last one edits a reference of the one before it."""
+    root = ASSETS / "flatten_masters"
+    save(root / "A.esm", [misc("rock"), interior("Hall", [reference(1, "rock", (100, 0, 0))])], esm=True)
+    save(root / "B.esm", [misc("vase"), interior("Hall", [reference(1, "vase", (200, 0, 0))])], ["A.esm"], esm=True)
+    hall = interior("Hall", [reference(1, "vase", (300, 0, 0), mast_index=2), reference(1, "rock", (400, 0, 0))])
+    save(root / "C.esm", [hall], ["A.esm", "B.esm"], esm=True)
+
+
+@fixture
+def later_masters():
+    """A plugin gives its new NPC an item of a master that follows the merge target.
+
+    The plugin also edits an exterior and a topic of the merge target, which the later master overrides.
+    """
+    root = ASSETS / "later_masters"
+    shared = [exterior((0, 0)), dialogue("greeting")]
+    save(root / "Master.esm", [misc("master_item"), *shared], esm=True)
+    save(root / "Later.esm", [misc("later_item"), *shared], esm=True)
+    items = [(1, "master_item"), (2, "later_item")]
+    save(root / "Plugin.esp", [npc("merchant", inventory=items), *shared], ["Master.esm", "Later.esm"])
+
+
+@fixture
+def residual():
+    """A plugin with a new NPC, and an exterior that uses a new landscape texture."""
+    root = ASSETS / "residual"
+    save(root / "Master.esm", [misc("rock")], esm=True)
+    save(
+        root / "Plugin.esp",
+        [
+            texture("tex_a", 0),
+            npc("merchant"),
+            exterior((0, 0), [reference(1, "rock", position((0, 0)))]),
+            landscape((0, 0), [(1, 256)]),
+        ],
+        ["Master.esm"],
+    )
+
+
+@fixture
+def remerge():
+    """A master that a previous version of the plugin was merged into, see `MergeManifest`.
+
+    The manifest is from a version that did not record the hash of the plugin.
+    """
+    root = ASSETS / "remerge"
+    shop = interior("Shop", [reference(1, "rock"), reference(5, "stale_item")])
+    objects = [misc("rock"), misc("stale_item"), misc("kept_item"), npc("guard"), shop]
+    save(root / "Master.esm", objects, esm=True)
+    objects = [misc("kept_item"), npc("guard"), interior("Shop", [reference(1, "kept_item")])]
+    save(root / "Plugin.esp", objects, ["Master.esm"])
+    records = [{"Object": ["", id]} for id in ("guard", "kept_item", "stale_item")]
+    manifest = {"entries": [{"plugin": "Plugin.esp", "records": records, "references": [5]}]}
+    (root / "Master.esm.manifest.json").write_text(json.dumps(manifest, indent=2) + "\n")
+
+
+@fixture
+def moved_references():
+    """A plugin's new reference that was moved from its exterior into the next one."""
+    root = ASSETS / "moved_references"
+    save(root / "Master.esm", [misc("rock"), exterior((0, 0)), exterior((1, 0))], esm=True)
+    moved = reference(1, "rock", position((1, 0)), moved_cell=(1, 0))
+    save(root / "Plugin.esp", [exterior((0, 0), [moved])], ["Master.esm"])
+
+
+@fixture
+def relocate_references():
+    """A plugin's new references that lie outside of their exterior, in a defined and undefined cell."""
+    root = ASSETS / "relocate_references"
+    save(root / "Master.esm", [misc("rock"), misc("boulder"), exterior((0, 0)), exterior((1, 0))], esm=True)
+    references = [reference(1, "rock", position((1, 0))), reference(2, "boulder", position((2, 0)))]
+    save(root / "Plugin.esp", [exterior((0, 0), references)], ["Master.esm"])
+
+
+@fixture
+def duplicate_references():
+    """A master that duplicates a reference of another master, and one of its own at a larger scale."""
+    root = ASSETS / "duplicate_references"
+    save(root / "Other.esm", [misc("rock"), interior("Hall", [reference(1, "rock", (100, 0, 0))])], esm=True)
+    hall = interior(
+        "Hall",
+        [
+            reference(1, "rock", (100, 0, 0), mast_index=1),
+            reference(1, "rock", (100, 0, 0)),
+            reference(2, "vase", (200, 0, 0)),
+            reference(3, "vase", (200, 0, 0), scale=2.0),
+        ],
+    )
+    save(root / "Master.esm", [misc("vase"), hall], ["Other.esm"], esm=True)
+    save(root / "Plugin.esp", [misc("vase")], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def border_duplicates():
+    """A master that duplicates a reference of another master, in the neighboring exterior."""
+    root = ASSETS / "border_duplicates"
+    translation = position((1, 0), (10, 4096, 0))
+    save(root / "Other.esm", [misc("rock"), exterior((1, 0), [reference(1, "rock", translation)])], esm=True)
+    save(root / "Master.esm", [exterior((0, 0), [reference(1, "rock", translation)])], ["Other.esm"], esm=True)
+    save(root / "Plugin.esp", [misc("vase")], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def clean_references():
+    """A plugin deletes the owner, key, trap and destination of the master's references."""
+    root = ASSETS / "clean_references"
+    vault = interior(
+        "Vault",
+        [
+            reference(1, "gold_item", owner="owner_npc", key="key_item", trap="trap_spell"),
+            reference(2, "door", destination=("Gone", (0, 0, 0))),
+        ],
+    )
+    objects = [misc("key_item"), misc("gold_item"), spell("trap_spell"), npc("owner_npc"), door("door")]
+    save(root / "Master.esm", [*objects, vault, interior("Gone")], esm=True)
+    deletions = [misc("key_item", deleted=True), spell("trap_spell", deleted=True), npc("owner_npc", deleted=True)]
+    save(root / "Plugin.esp", [*deletions, interior("Gone", deleted=True)], ["Master.esm"])
+
+
+@fixture
+def clean_infos():
+    """A plugin deletes an NPC of another master, who speaks in topics of both masters."""
+    root = ASSETS / "clean_infos"
+    save(
+        root / "Other.esm",
+        [npc("speaker_npc"), dialogue("OtherTopic"), info("o1", text="Other", speaker="speaker_npc")],
+        esm=True,
+    )
+    save(
+        root / "Master.esm",
+        [
+            npc("keeper"),
+            dialogue("Topic"),
+            info("m1", "", "m2", "Deleted speaker", speaker="speaker_npc"),
+            info("m2", "m1", "", "Other speaker", speaker="keeper"),
+        ],
+        ["Other.esm"],
+        esm=True,
+    )
+    save(root / "Plugin.esp", [npc("speaker_npc", deleted=True)], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def clean_fallbacks():
+    """A plugin deletes the race and class of an NPC, and the race of a body part."""
+    root = ASSETS / "clean_fallbacks"
+    objects = [
+        race("Race"),
+        race("RaceKeep"),
+        class_("Class"),
+        class_("ClassKeep"),
+        npc("npc", race="Race", class_="Class"),
+        bodypart("race_part", "Race"),
+        bodypart("keep_part", "RaceKeep"),
+    ]
+    save(root / "Master.esm", objects, esm=True)
+    save(root / "Plugin.esp", [race("Race", deleted=True), class_("Class", deleted=True)], ["Master.esm"])
+
+
+@fixture
+def clean_texture_indices():
+    """A plugin deletes a landscape texture that is used by the master's landscape."""
+    root = ASSETS / "clean_texture_indices"
+    objects = [
+        texture("tex_gone", 0),
+        texture("tex_keep", 1),
+        exterior((0, 0)),
+        landscape((0, 0), [(1, 100), (2, 50)]),
+    ]
+    save(root / "Master.esm", objects, esm=True)
+    save(root / "Plugin.esp", [texture("tex_gone", 0, deleted=True)], ["Master.esm"])
+
+
+@fixture
+def safe_delete():
+    """A plugin deletes a reference of another master."""
+    root = ASSETS / "safe_delete"
+    save(root / "Other.esm", [misc("rock"), interior("Hall", [reference(1, "rock", (100, 200, 300))])], esm=True)
+    save(root / "Master.esm", [misc("vase")], ["Other.esm"], esm=True)
+    hall = interior("Hall", [reference(1, "rock", mast_index=1, deleted=True)])
+    save(root / "Plugin.esp", [hall], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def replace_ids():
+    """A plugin's NPC carries an item that is also placed in a cell of the master."""
+    root = ASSETS / "replace_ids"
+    shop = interior("Shop", [reference(1, "old_item")])
+    save(root / "Master.esm", [misc("old_item"), misc("new_item"), shop], esm=True)
+    save(root / "Plugin.esp", [npc("merchant", inventory=[(3, "old_item")])], ["Master.esm"])
+
+
+@fixture
+def remove_unused():
+    """A master with used and unused records, some of which override another master."""
+    root = ASSETS / "remove_unused"
+    save(root / "Other.esm", [texture("other_tex", 0), enchantment("other_ench")], esm=True)
+    objects = [
+        texture("other_tex", 0),
+        texture("unused_tex", 1),
+        texture("used_tex", 2),
+        enchantment("other_ench"),
+        enchantment("unused_ench"),
+        enchantment("used_ench"),
+        bodypart("unused_part", "Race", part_type=2),
+        bodypart("used_part", "Race", part_type=2),
+        armor("cuirass", "used_part", "used_ench"),
+        creature("beast"),
+        sound_gen("beast_roar", "beast"),
+        exterior((0, 0)),
+        landscape((0, 0), [(3, 256)]),
+    ]
+    save(root / "Master.esm", objects, ["Other.esm"], esm=True)
+    save(root / "Plugin.esp", [creature("beast", deleted=True)], ["Other.esm", "Master.esm"])
+
+
+@fixture
+def dedupe_textures():
+    """Landscape textures of the master and plugin that use the texture file of another master."""
+    root = ASSETS / "dedupe_textures"
+    save(root / "Other.esm", [texture("tex_o", 0, "a.dds")], esm=True)
+    objects = [
+        texture("tex_o", 0, "a.dds"),
+        texture("tex_a", 1, "a.dds"),
+        texture("tex_c", 2, "c.dds"),
+        exterior((0, 0)),
+        landscape((0, 0), [(2, 100), (3, 156)]),
+    ]
+    save(root / "Master.esm", objects, ["Other.esm"], esm=True)
+    objects = [texture("tex_b", 0, "A.DDS"), exterior((1, 0)), landscape((1, 0), [(1, 256)])]
+    save(root / "Plugin.esp", objects, ["Other.esm", "Master.esm"])
+
+
+@fixture
+def texture_overflow():
+    """A plugin with a new landscape texture, merged into a master whose indices are all in use.
+
+    That master would be several megabytes, so the test builds it in memory instead.
+    """
+    root = ASSETS / "texture_overflow"
+    save(root / "Plugin.esp", [texture("tex_new", 0), exterior((0, 0)), landscape((0, 0), [(1, 256)])])
+
+
+@fixture
+def texture_compaction():
+    """A plugin with a new landscape texture, merged into a master whose indices have a large gap."""
+    root = ASSETS / "texture_compaction"
+    objects = [
+        texture("tex_low", 0),
+        texture("tex_high", 0xFFFE),
+        exterior((1, 0)),
+        landscape((1, 0), [(1, 128), (0xFFFF, 128)]),
+    ]
+    save(root / "Master.esm", objects, esm=True)
+    plugin = [texture("tex_new", 0), exterior((0, 0)), landscape((0, 0), [(1, 256)])]
+    save(root / "Plugin.esp", plugin, ["Master.esm"])
+
+
+@fixture
+def grid_offset():
+    """A plugin's exterior, with doors and an NPC whose destinations are in it or elsewhere."""
+    root = ASSETS / "grid_offset"
+    save(root / "Master.esm", [misc("rock"), door("door_in"), door("door_far")], esm=True)
+    house = interior(
+        "House",
+        [
+            reference(1, "door_in", destination=("", position((0, 0), (100, 200, 300)))),
+            reference(2, "door_far", destination=("", position((9, 9)))),
+        ],
+    )
+    objects = [
+        npc("guide", destinations=[("", position((0, 0), (1, 2, 3)))]),
+        exterior((0, 0), [reference(3, "rock", position((0, 0)))]),
+        landscape((0, 0)),
+        house,
+    ]
+    save(root / "Plugin.esp", objects, ["Master.esm"])
+
+
+@fixture
+def rename_interiors():
+    """A master whose interior is the destination of doors, travel, AI packages and dialogue."""
+    root = ASSETS / "rename_interiors"
+    packages = [("AI_E", "player", "Bar"), ("AI_F", "player", "Bar")]
+    objects = [
+        misc("rock"),
+        door("door_bar"),
+        npc("guide", destinations=[("Bar", (1, 2, 3))], packages=packages),
+        interior("Bar", [reference(1, "rock")]),
+        pathgrid("Bar"),
+        interior("Hall", [reference(2, "door_bar", destination=("Bar", (0, 0, 0)))]),
+        dialogue("Greeting"),
+        info("i1", "", "i2", "Welcome to the bar", cell="Bar"),
+        info("i2", "i1", "", "Visit the bar", not_cells=["Bar"]),
+    ]
+    save(root / "Master.esm", objects, esm=True)
+    save(root / "Plugin.esp", [misc("vase")], ["Master.esm"])
+
+
+if __name__ == "__main__":
+    for name in sys.argv[1:] or FIXTURES:
+        FIXTURES[name]()
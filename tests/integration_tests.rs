@@ -2,8 +2,22 @@ use merge_to_master::prelude::*;
 
 const OPTIONS: MergeOptions = MergeOptions {
     remove_deleted: false,
-    apply_moved_references: false,
+    replace_ids: Vec::new(),
+    rename_interiors: Vec::new(),
+    clean: CleanOptions::DEFAULT,
+    moved_references: MovedReferences::Keep,
+    relocate_references: false,
     preserve_duplicate_references: false,
+    duplicate_references: DuplicateReferences::DEFAULT,
+    report_conflicts: false,
+    later_masters: LaterMasters::Refuse,
+    filter: MergeFilter::NONE,
+    residual: false,
+    remerge: false,
+    annotate_header: false,
+    dedupe_textures: false,
+    remove_unused: false,
+    grid_offset: (0, 0),
 };
 
 const REMOVE_DELETED: MergeOptions = MergeOptions {
@@ -240,6 +254,1048 @@ fn info_delete_middle_2() {
     assert_eq!(merged_bytes, expect_bytes);
 }
 
+#[test]
+fn report_conflicts() {
+    let plugin_path = PathBuf::from("./tests/assets/conflicts/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/conflicts/Master.esm");
+
+    let options = MergeOptions {
+        report_conflicts: true,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let conflicts = output
+        .report
+        .conflicts
+        .iter()
+        .map(|conflict| {
+            (
+                conflict.key.to_string(),
+                conflict.masters.clone(),
+                conflict.overrides_other_master,
+            )
+        })
+        .collect_vec();
+
+    assert_eq!(
+        conflicts,
+        [
+            ("OBJ other_only".to_owned(), vec!["Other.esm".to_owned()], true),
+            (
+                "OBJ shared".to_owned(),
+                vec!["Other.esm".to_owned(), "Master.esm".to_owned()],
+                false
+            ),
+        ]
+    );
+}
+
+#[test]
+fn load_order_conflicts() {
+    let assets = PathBuf::from("./tests/assets/conflicts");
+    let paths = ["Other.esm", "Master.esm", "Plugin.esp"].map(|name| assets.join(name));
+
+    let matrix = find_load_order_conflicts(&paths).unwrap();
+
+    assert_eq!(matrix.files, ["Other.esm", "Master.esm", "Plugin.esp"]);
+
+    let conflicts = matrix
+        .conflicts
+        .iter()
+        .map(|conflict| (conflict.key.to_string(), conflict.files.clone(), conflict.winner()))
+        .collect_vec();
+
+    assert_eq!(
+        conflicts,
+        [
+            ("OBJ other_only".to_owned(), vec![0, 2], 2),
+            ("OBJ shared".to_owned(), vec![0, 1, 2], 2),
+        ]
+    );
+}
+
+#[test]
+fn later_masters_refuse() {
+    let plugin_path = PathBuf::from("./tests/assets/later_masters/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/later_masters/Master.esm");
+
+    let error = merge_plugins(&plugin_path, &master_path, OPTIONS).err().unwrap();
+
+    assert!(error.to_string().contains("OBJ merchant"));
+}
+
+#[test]
+fn later_masters_carry() {
+    let plugin_path = PathBuf::from("./tests/assets/later_masters/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/later_masters/Master.esm");
+
+    let options = MergeOptions {
+        later_masters: LaterMasters::Carry,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    // Only the inventory item of the later master is a dependency.
+    let [dependency] = &output.report.later_master_dependencies[..] else {
+        panic!("expected a single dependency");
+    };
+    assert_eq!(dependency.master, "Later.esm");
+    assert_eq!(dependency.record.to_string(), "OBJ merchant");
+    assert_eq!(dependency.uses.as_deref(), Some("later_item"));
+
+    let masters = output
+        .master
+        .header
+        .masters
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect_vec();
+    assert_eq!(masters, ["Later.esm"]);
+}
+
+#[test]
+fn residual() {
+    let plugin_path = PathBuf::from("./tests/assets/residual/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/residual/Master.esm");
+
+    let mut filter = MergeFilter::NONE;
+    filter.exclude.exteriors.push(GridRect {
+        min: (0, 0),
+        max: (0, 0),
+    });
+
+    let options = MergeOptions {
+        filter,
+        residual: true,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let excluded = output.report.excluded.iter().map(ToString::to_string).collect_vec();
+    assert_eq!(excluded, ["CELL (0, 0)", "LAND (0, 0)"]);
+
+    use tes3::esp::*;
+
+    let texture_key = (LandscapeTexture::TAG, "tex_a".to_owned());
+
+    // The excluded landscape keeps its texture, which is still merged.
+    let master = output.master;
+    assert!(master.objects.contains_key(&(&[0; 4], "merchant".to_owned())));
+    assert!(master.objects.contains_key(&texture_key));
+    assert!(master.cells.get_exterior((0, 0)).is_none());
+
+    let residual = output.residual.unwrap();
+    let masters = residual
+        .header
+        .masters
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect_vec();
+    assert_eq!(masters, ["Master.esm"]);
+    assert!(residual.objects.contains_key(&texture_key));
+
+    let exterior = residual.cells.get_exterior((0, 0)).unwrap();
+    assert!(exterior.landscape.is_some());
+    assert_eq!(exterior.cell.as_ref().unwrap().references.len(), 1);
+
+    // Without a filter there would be nothing to save.
+    let options = MergeOptions {
+        residual: true,
+        ..OPTIONS
+    };
+    assert!(merge_plugins(&plugin_path, &master_path, options).is_err());
+}
+
+#[test]
+fn remerge() {
+    let plugin_path = PathBuf::from("./tests/assets/remerge/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/remerge/Master.esm");
+
+    let mut filter = MergeFilter::NONE;
+    filter.exclude.tags.push("NPC_".into());
+
+    let options = MergeOptions {
+        remerge: true,
+        filter,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    // The excluded record is still part of the plugin, so it is not stale.
+    let master = output.master;
+    assert!(master.objects.contains_key(&(&[0; 4], "rock".to_owned())));
+    assert!(master.objects.contains_key(&(&[0; 4], "kept_item".to_owned())));
+    assert!(master.objects.contains_key(&(&[0; 4], "guard".to_owned())));
+    assert!(!master.objects.contains_key(&(&[0; 4], "stale_item".to_owned())));
+
+    // Only the reference added by the previous merge is removed, and its index is not reused.
+    let shop = master.cells.get_interior("Shop").unwrap().cell.as_ref().unwrap();
+    assert_eq!(shop.references.keys().sorted().collect_vec(), [&(0, 1), &(0, 6)]);
+
+    // Records still added by the plugin are carried over to the new entry.
+    let records = output
+        .manifest_entry
+        .records
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec();
+    assert_eq!(records, ["OBJ kept_item"]);
+}
+
+#[test]
+fn moved_references_apply() {
+    let plugin_path = PathBuf::from("./tests/assets/moved_references/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/moved_references/Master.esm");
+
+    let options = MergeOptions {
+        moved_references: MovedReferences::Apply,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let [moved] = &output.report.moved_references[..] else {
+        panic!("expected a single moved reference");
+    };
+    assert_eq!(moved.destination, (1, 0));
+    assert_eq!(moved.result, MoveResult::Applied);
+
+    let source = output.master.cells.get_exterior((0, 0)).unwrap().cell.as_ref().unwrap();
+    assert!(source.references.is_empty());
+
+    let target = output.master.cells.get_exterior((1, 0)).unwrap().cell.as_ref().unwrap();
+    let [reference] = &target.references.values().collect_vec()[..] else {
+        panic!("expected a single reference");
+    };
+    assert_eq!(reference.id, "rock");
+    assert_eq!(reference.moved_cell, None);
+}
+
+#[test]
+fn moved_references_drop() {
+    let plugin_path = PathBuf::from("./tests/assets/moved_references/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/moved_references/Master.esm");
+
+    let options = MergeOptions {
+        moved_references: MovedReferences::Drop,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let [moved] = &output.report.moved_references[..] else {
+        panic!("expected a single moved reference");
+    };
+    assert_eq!(moved.result, MoveResult::Dropped);
+
+    for coords in [(0, 0), (1, 0)] {
+        let cell = output.master.cells.get_exterior(coords).unwrap().cell.as_ref().unwrap();
+        assert!(cell.references.is_empty());
+    }
+}
+
+#[test]
+fn relocate_references() {
+    let plugin_path = PathBuf::from("./tests/assets/relocate_references/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/relocate_references/Master.esm");
+
+    let options = MergeOptions {
+        relocate_references: true,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let relocations = output
+        .report
+        .relocations
+        .iter()
+        .map(|relocation| {
+            (
+                relocation.id.as_str(),
+                relocation.from,
+                relocation.to,
+                relocation.moved_reference,
+            )
+        })
+        .sorted()
+        .collect_vec();
+
+    // The master does not define the boulder's cell, so it becomes a moved reference instead.
+    assert_eq!(
+        relocations,
+        [("boulder", (0, 0), (2, 0), true), ("rock", (0, 0), (1, 0), false)]
+    );
+
+    let source = output.master.cells.get_exterior((0, 0)).unwrap().cell.as_ref().unwrap();
+    let [boulder] = &source.references.values().collect_vec()[..] else {
+        panic!("expected a single reference");
+    };
+    assert_eq!(boulder.id, "boulder");
+    assert_eq!(boulder.moved_cell, Some((2, 0)));
+
+    let target = output.master.cells.get_exterior((1, 0)).unwrap().cell.as_ref().unwrap();
+    assert_eq!(
+        target.references.values().map(|reference| &reference.id).collect_vec(),
+        ["rock"]
+    );
+}
+
+#[test]
+fn duplicate_references() {
+    let plugin_path = PathBuf::from("./tests/assets/duplicate_references/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/duplicate_references/Master.esm");
+
+    // The reference of the other master is kept over the master's own duplicate of it,
+    // while references at different scales are not duplicates.
+    let merged = merge_plugins(&plugin_path, &master_path, OPTIONS).unwrap();
+    let hall = merged.cells.get_interior("Hall").unwrap().cell.as_ref().unwrap();
+    assert_eq!(
+        hall.references.keys().sorted().collect_vec(),
+        [&(0, 2), &(0, 3), &(1, 1)]
+    );
+
+    // Unless scales are not compared.
+    let options = MergeOptions {
+        duplicate_references: DuplicateReferences {
+            compare_scale: false,
+            ..DuplicateReferences::DEFAULT
+        },
+        ..OPTIONS
+    };
+    let merged = merge_plugins(&plugin_path, &master_path, options).unwrap();
+    let hall = merged.cells.get_interior("Hall").unwrap().cell.as_ref().unwrap();
+    assert_eq!(hall.references.keys().sorted().collect_vec(), [&(0, 2), &(1, 1)]);
+}
+
+#[test]
+fn border_duplicates() {
+    let plugin_path = PathBuf::from("./tests/assets/border_duplicates/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/border_duplicates/Master.esm");
+
+    let options = MergeOptions {
+        duplicate_references: DuplicateReferences {
+            cross_border: CrossBorder::Remove,
+            ..DuplicateReferences::DEFAULT
+        },
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    // The reference of the other master is kept, even though it is in the later cell.
+    let [duplicate] = &output.report.border_duplicates[..] else {
+        panic!("expected a single border duplicate");
+    };
+    assert_eq!(duplicate.id, "rock");
+    assert_eq!(duplicate.original.to_string(), "REFR (1, 0) (other.esm #1)");
+    assert_eq!(duplicate.duplicate.to_string(), "REFR (0, 0) (master.esm #1)");
+    assert!(duplicate.removed);
+
+    let cell = output.master.cells.get_exterior((0, 0)).unwrap().cell.as_ref().unwrap();
+    assert!(cell.references.is_empty());
+}
+
+#[test]
+fn clean_references() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_references/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_references/Master.esm");
+
+    let output = merge_plugins_with_report(&plugin_path, &master_path, REMOVE_DELETED).unwrap();
+
+    let deleted = output
+        .report
+        .cleaned
+        .deleted
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec();
+    assert_eq!(
+        deleted,
+        ["OBJ key_item", "OBJ owner_npc", "OBJ trap_spell", "CELL 'gone'"]
+    );
+
+    let fields = output
+        .report
+        .cleaned
+        .fields
+        .iter()
+        .map(|field| (field.record.as_str(), field.field.as_str(), field.id.as_str()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(
+        fields,
+        [
+            ("CELL 'Vault' REFR 'door' (0, 2)", "destination", "Gone"),
+            ("CELL 'Vault' REFR 'gold_item' (0, 1)", "key", "key_item"),
+            ("CELL 'Vault' REFR 'gold_item' (0, 1)", "owner", "owner_npc"),
+            ("CELL 'Vault' REFR 'gold_item' (0, 1)", "trap", "trap_spell"),
+        ]
+    );
+
+    let vault = output
+        .master
+        .cells
+        .get_interior("Vault")
+        .unwrap()
+        .cell
+        .as_ref()
+        .unwrap();
+    let gold = &vault.references[&(0, 1)];
+    assert_eq!((&gold.owner, &gold.key, &gold.trap), (&None, &None, &None));
+    assert!(vault.references[&(0, 2)].destination.is_none());
+    assert!(output.master.cells.get_interior("Gone").is_none());
+}
+
+#[test]
+fn clean_infos() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_infos/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_infos/Master.esm");
+
+    let output = merge_plugins_with_report(&plugin_path, &master_path, REMOVE_DELETED).unwrap();
+
+    // Infos of the other master are not part of the result, so they are left as is.
+    let [cleaned] = &output.report.cleaned.infos[..] else {
+        panic!("expected a single cleaned info");
+    };
+    assert_eq!((cleaned.topic.as_str(), cleaned.info.as_str()), ("topic", "m1"));
+    assert_eq!(cleaned.fields, ["speaker_id 'speaker_npc'"]);
+    assert_eq!(cleaned.action, DeletedInfoReferences::Clean);
+
+    let infos = &output.master.dialogues["topic"].infos;
+    assert_eq!(
+        infos.iter().map(|info| info.speaker_id.as_str()).collect_vec(),
+        ["", "keeper"]
+    );
+}
+
+#[test]
+fn clean_infos_remove() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_infos/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_infos/Master.esm");
+
+    let options = MergeOptions {
+        clean: CleanOptions {
+            infos: DeletedInfoReferences::Remove,
+            ..CleanOptions::DEFAULT
+        },
+        ..REMOVE_DELETED
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    assert_eq!(output.report.cleaned.infos.len(), 1);
+
+    let infos = &output.master.dialogues["topic"].infos;
+    assert_eq!(infos.iter().map(|info| info.id.as_str()).collect_vec(), ["m2"]);
+    assert_eq!(infos[0].prev_id, "");
+}
+
+#[test]
+fn clean_fallbacks() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_fallbacks/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_fallbacks/Master.esm");
+
+    let options = MergeOptions {
+        clean: CleanOptions {
+            fallback_race: Some("RaceKeep".into()),
+            fallback_class: Some("ClassKeep".into()),
+            ..CleanOptions::DEFAULT
+        },
+        ..REMOVE_DELETED
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    use tes3::esp::*;
+
+    let Some(TES3Object::Npc(npc)) = output.master.objects.get(&(&[0; 4], "npc".to_owned())) else {
+        panic!("expected the npc");
+    };
+    assert_eq!((npc.race.as_str(), npc.class.as_str()), ("RaceKeep", "ClassKeep"));
+
+    let fallbacks = output
+        .report
+        .cleaned
+        .fallbacks
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec();
+    assert_eq!(
+        fallbacks,
+        [
+            "NPC_ npc: class 'Class' replaced with 'ClassKeep'",
+            "NPC_ npc: race 'Race' replaced with 'RaceKeep'",
+        ]
+    );
+
+    // Body parts of the deleted race are removed along with it.
+    let bodyparts = output
+        .report
+        .cleaned
+        .bodyparts
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec();
+    assert_eq!(bodyparts, ["OBJ race_part"]);
+    assert!(output.master.objects.contains_key(&(&[0; 4], "keep_part".to_owned())));
+
+    // Fallbacks must exist, and not be deleted themselves.
+    for fallback_class in ["Missing", "Class"] {
+        let options = MergeOptions {
+            clean: CleanOptions {
+                fallback_class: Some(fallback_class.into()),
+                ..CleanOptions::DEFAULT
+            },
+            ..REMOVE_DELETED
+        };
+        assert!(merge_plugins(&plugin_path, &master_path, options).is_err());
+    }
+}
+
+#[test]
+fn clean_without_fallbacks() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_fallbacks/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_fallbacks/Master.esm");
+
+    let merged = merge_plugins(&plugin_path, &master_path, REMOVE_DELETED).unwrap();
+
+    use tes3::esp::*;
+
+    // Without a fallback the race is left as is, as NPCs without a race crash the TESCS.
+    let Some(TES3Object::Npc(npc)) = merged.objects.get(&(&[0; 4], "npc".to_owned())) else {
+        panic!("expected the npc");
+    };
+    assert_eq!((npc.race.as_str(), npc.class.as_str()), ("Race", ""));
+}
+
+#[test]
+fn clean_texture_indices() {
+    let plugin_path = PathBuf::from("./tests/assets/clean_texture_indices/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/clean_texture_indices/Master.esm");
+
+    let count_indices = |master: &PluginData, index| {
+        let exterior = master.cells.get_exterior((0, 0)).unwrap();
+        let landscape = exterior.landscape.as_ref().unwrap();
+        landscape
+            .texture_indices
+            .data
+            .as_flattened()
+            .iter()
+            .filter(|&&i| i == index)
+            .count()
+    };
+
+    // Without a fallback the default texture is used.
+    let output = merge_plugins_with_report(&plugin_path, &master_path, REMOVE_DELETED).unwrap();
+    let [cleaned] = &output.report.cleaned.textures[..] else {
+        panic!("expected a single cleaned landscape");
+    };
+    assert_eq!(
+        (cleaned.grid, cleaned.count, cleaned.replacement.as_deref()),
+        ((0, 0), 100, None)
+    );
+    assert_eq!(count_indices(&output.master, 0), 206);
+    assert_eq!(count_indices(&output.master, 2), 50);
+
+    let options = MergeOptions {
+        clean: CleanOptions {
+            fallback_texture: Some("tex_keep".into()),
+            ..CleanOptions::DEFAULT
+        },
+        ..REMOVE_DELETED
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+    let [cleaned] = &output.report.cleaned.textures[..] else {
+        panic!("expected a single cleaned landscape");
+    };
+    assert_eq!(cleaned.replacement.as_deref(), Some("tex_keep"));
+    assert_eq!(count_indices(&output.master, 0), 106);
+    assert_eq!(count_indices(&output.master, 2), 150);
+}
+
+#[test]
+fn safe_delete() {
+    let plugin_path = PathBuf::from("./tests/assets/safe_delete/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/safe_delete/Master.esm");
+
+    let options = MergeOptions {
+        clean: CleanOptions {
+            safe_delete: true,
+            ..CleanOptions::DEFAULT
+        },
+        ..REMOVE_DELETED
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let [safe_deleted] = &output.report.cleaned.safe_deleted[..] else {
+        panic!("expected a single safe deleted reference");
+    };
+    assert_eq!(safe_deleted.cell, "Hall");
+    assert_eq!(safe_deleted.id, "rock");
+    assert_eq!((safe_deleted.mast_index, safe_deleted.refr_index), (1, 1));
+
+    use tes3::esp::*;
+
+    let hall = output.master.cells.get_interior("Hall").unwrap().cell.as_ref().unwrap();
+    let reference = &hall.references[&(1, 1)];
+    assert!(!reference.deleted());
+    assert_eq!(reference.blocked, Some(1));
+    assert_eq!(reference.translation[2], -32768.0);
+    assert_eq!(reference.scale, Some(0.5));
+}
+
+#[test]
+fn replace_ids() {
+    let plugin_path = PathBuf::from("./tests/assets/replace_ids/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/replace_ids/Master.esm");
+
+    let options = MergeOptions {
+        replace_ids: vec![("Old_Item".into(), "new_item".into())],
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let replaced = output
+        .report
+        .replaced_ids
+        .iter()
+        .map(|replaced| (replaced.record.as_str(), replaced.field.as_str(), replaced.new.as_str()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(
+        replaced,
+        [
+            ("CELL 'Shop'", "id", "new_item"),
+            ("NPC_ merchant", "inventory", "new_item")
+        ]
+    );
+
+    use tes3::esp::*;
+
+    let Some(TES3Object::Npc(npc)) = output.master.objects.get(&(&[0; 4], "merchant".to_owned())) else {
+        panic!("expected the merchant");
+    };
+    assert_eq!(npc.inventory[0].1.as_str(), "new_item");
+
+    let shop = output.master.cells.get_interior("Shop").unwrap().cell.as_ref().unwrap();
+    assert_eq!(shop.references[&(0, 1)].id, "new_item");
+
+    // The replaced object itself is left as is.
+    assert!(output.master.objects.contains_key(&(&[0; 4], "old_item".to_owned())));
+
+    // Inventory items are saved with at most 32 characters, which the cell reference is not.
+    let options = MergeOptions {
+        replace_ids: vec![("old_item".into(), "a".repeat(33))],
+        ..OPTIONS
+    };
+    let error = merge_plugins(&plugin_path, &master_path, options).unwrap_err();
+    assert!(error.to_string().contains("NPC_ merchant: inventory 'old_item'"));
+    assert!(!error.to_string().contains("CELL 'Shop'"));
+}
+
+#[test]
+fn usage_index() {
+    let master_path = PathBuf::from("./tests/assets/remove_deleted_fields/Master.esm");
+    let master = PluginData::from_path(&master_path).unwrap();
+
+    let index = master.usage_index("Master.esm");
+    let uses = index
+        .get("containeritem")
+        .iter()
+        .map(|usage| (usage.record.as_str(), usage.field.as_str()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(
+        uses,
+        [
+            ("CONT Container", "inventory"),
+            ("CREA Creature_Keep", "inventory"),
+            ("LEVI LeveledItem", "items"),
+            ("NPC_ NPC", "inventory"),
+            ("NPC_ NPC_Keep", "inventory"),
+        ]
+    );
+
+    // Uses by references are keyed by the reference rather than its cell.
+    let master_path = PathBuf::from("./tests/assets/clean_references/Master.esm");
+    let master = PluginData::from_path(&master_path).unwrap();
+
+    let index = master.usage_index("Master.esm");
+    let [usage] = index.get("owner_npc") else {
+        panic!("expected a single use");
+    };
+    assert_eq!(usage.key.to_string(), "REFR 'vault' (master.esm #1)");
+    assert_eq!(usage.record, "CELL 'Vault' REFR 'gold_item' (0, 1)");
+    assert_eq!(usage.field, "owner");
+}
+
+#[test]
+fn remove_unused() {
+    let plugin_path = PathBuf::from("./tests/assets/remove_unused/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/remove_unused/Master.esm");
+
+    let options = MergeOptions {
+        remove_unused: true,
+        ..REMOVE_DELETED
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    // Records that override the other master are kept, even though nothing uses them.
+    let unused = output.report.unused.iter().map(ToString::to_string).collect_vec();
+    assert_eq!(unused, ["OBJ unused_ench", "OBJ unused_part", "LTEX unused_tex"]);
+
+    let sound_gens = output
+        .report
+        .cleaned
+        .sound_gens
+        .iter()
+        .map(ToString::to_string)
+        .collect_vec();
+    assert_eq!(sound_gens, ["SNDG beast_roar"]);
+
+    use tes3::esp::*;
+
+    let master = output.master;
+    for id in ["other_ench", "used_ench", "used_part"] {
+        assert!(master.objects.contains_key(&(&[0; 4], id.to_owned())));
+    }
+
+    // The remaining textures are compacted.
+    let Some(TES3Object::LandscapeTexture(texture)) =
+        master.objects.get(&(LandscapeTexture::TAG, "used_tex".to_owned()))
+    else {
+        panic!("expected the used texture");
+    };
+    assert_eq!(texture.index, 1);
+    let landscape = master.cells.get_exterior((0, 0)).unwrap().landscape.as_ref().unwrap();
+    assert!(
+        landscape
+            .texture_indices
+            .data
+            .as_flattened()
+            .iter()
+            .all(|&index| index == 2)
+    );
+}
+
+#[test]
+fn dedupe_textures() {
+    let plugin_path = PathBuf::from("./tests/assets/dedupe_textures/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/dedupe_textures/Master.esm");
+
+    let options = MergeOptions {
+        dedupe_textures: true,
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    // The texture that overrides the other master is kept, even though it has a higher index.
+    let duplicates = output
+        .report
+        .duplicate_textures
+        .iter()
+        .map(|duplicate| (duplicate.id.as_str(), duplicate.original.as_str()))
+        .collect_vec();
+    assert_eq!(duplicates, [("tex_a", "tex_o"), ("tex_b", "tex_o")]);
+
+    use tes3::esp::*;
+
+    let master = output.master;
+    let textures = master
+        .objects
+        .values()
+        .filter_map(|object| match object {
+            TES3Object::LandscapeTexture(texture) => Some((texture.id.as_str(), texture.index)),
+            _ => None,
+        })
+        .sorted()
+        .collect_vec();
+    assert_eq!(textures, [("tex_c", 1), ("tex_o", 0)]);
+
+    let count_indices = |coords, index| {
+        let landscape = master.cells.get_exterior(coords).unwrap().landscape.as_ref().unwrap();
+        landscape
+            .texture_indices
+            .data
+            .as_flattened()
+            .iter()
+            .filter(|&&i| i == index)
+            .count()
+    };
+    assert_eq!((count_indices((0, 0), 1), count_indices((0, 0), 2)), (100, 156));
+    assert_eq!(count_indices((1, 0), 1), 256);
+}
+
+#[test]
+fn texture_overflow() {
+    let plugin_path = PathBuf::from("./tests/assets/texture_overflow/Plugin.esp");
+    let mut plugin = PluginData::from_path(&plugin_path).unwrap();
+
+    use tes3::esp::*;
+
+    // A master whose textures use every index that landscapes can refer to.
+    let mut master = PluginData::default();
+    for index in 0..TEXTURE_INDEX_LIMIT {
+        let id = format!("tex_{index}");
+        let texture = LandscapeTexture {
+            id: id.clone(),
+            index,
+            file_name: format!("{id}.dds"),
+            ..default()
+        };
+        master
+            .objects
+            .insert((LandscapeTexture::TAG, id), TES3Object::LandscapeTexture(texture));
+    }
+
+    let result = plugin.remap_textures(&mut master);
+    assert!(matches!(
+        result,
+        Err(TextureError::TooManyTextures { required: 0x10000 })
+    ));
+}
+
+#[test]
+fn texture_compaction() {
+    let plugin_path = PathBuf::from("./tests/assets/texture_compaction/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/texture_compaction/Master.esm");
+
+    // The new texture only fits once the master's textures are compacted.
+    let output = merge_plugins_with_report(&plugin_path, &master_path, OPTIONS).unwrap();
+    assert_eq!(output.report.texture_indices, 3);
+
+    use tes3::esp::*;
+
+    let master = output.master;
+    let textures = master
+        .objects
+        .values()
+        .filter_map(|object| match object {
+            TES3Object::LandscapeTexture(texture) => Some((texture.id.as_str(), texture.index)),
+            _ => None,
+        })
+        .sorted()
+        .collect_vec();
+    assert_eq!(textures, [("tex_high", 1), ("tex_low", 0), ("tex_new", 2)]);
+
+    // Landscapes store indices plus one, where 0 is the default texture.
+    let indices = |coords| {
+        let landscape = master.cells.get_exterior(coords).unwrap().landscape.as_ref().unwrap();
+        landscape.texture_indices.data.as_flattened().iter().copied().counts()
+    };
+    assert_eq!(indices((1, 0)), [(1, 128), (2, 128)].into());
+    assert_eq!(indices((0, 0)), [(3, 256)].into());
+}
+
+#[test]
+fn grid_offset() {
+    let plugin_path = PathBuf::from("./tests/assets/grid_offset/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/grid_offset/Master.esm");
+
+    let options = MergeOptions {
+        grid_offset: (2, -1),
+        ..OPTIONS
+    };
+    let merged = merge_plugins(&plugin_path, &master_path, options).unwrap();
+
+    assert!(merged.cells.get_exterior((0, 0)).is_none());
+
+    let exterior = merged.cells.get_exterior((2, -1)).unwrap();
+    let cell = exterior.cell.as_ref().unwrap();
+    assert_eq!(cell.data.grid, (2, -1));
+    assert_eq!(exterior.landscape.as_ref().unwrap().grid, (2, -1));
+
+    let [rock] = &cell.references.values().collect_vec()[..] else {
+        panic!("expected a single reference");
+    };
+    assert_eq!(rock.translation, [20480.0, -4096.0, 0.0]);
+
+    // Only destinations within the moved exterior are offset.
+    let house = merged.cells.get_interior("House").unwrap().cell.as_ref().unwrap();
+    let destinations = house
+        .references
+        .values()
+        .map(|reference| {
+            (
+                reference.id.as_str(),
+                reference.destination.as_ref().unwrap().translation,
+            )
+        })
+        .sorted_by(|a, b| a.0.cmp(b.0))
+        .collect_vec();
+    assert_eq!(
+        destinations,
+        [
+            ("door_far", [77824.0, 77824.0, 0.0]),
+            ("door_in", [16484.0, -7992.0, 300.0]),
+        ]
+    );
+
+    use tes3::esp::*;
+
+    let Some(TES3Object::Npc(npc)) = merged.objects.get(&(&[0; 4], "guide".to_owned())) else {
+        panic!("expected the guide");
+    };
+    assert_eq!(npc.travel_destinations[0].translation, [16385.0, -8190.0, 3.0]);
+}
+
+#[test]
+fn rename_interiors() {
+    let plugin_path = PathBuf::from("./tests/assets/rename_cells/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/rename_cells/Master.esm");
+
+    let options = MergeOptions {
+        rename_interiors: vec![("bar".into(), "Qux".into())],
+        ..REMOVE_DELETED
+    };
+    let merged = merge_plugins(&plugin_path, &master_path, options).unwrap();
+
+    assert!(merged.cells.get_interior("Bar").is_none());
+
+    let interior = merged.cells.get_interior("qux").unwrap();
+    let cell = interior.cell.as_ref().unwrap();
+    assert_eq!(cell.name, "Qux");
+    assert!(!cell.references.is_empty());
+
+    // Renaming to the name of another interior would merge their contents.
+    let options = MergeOptions {
+        rename_interiors: vec![("Bar".into(), "Foo".into())],
+        ..REMOVE_DELETED
+    };
+    assert!(merge_plugins(&plugin_path, &master_path, options).is_err());
+}
+
+#[test]
+fn rename_interiors_uses() {
+    let plugin_path = PathBuf::from("./tests/assets/rename_interiors/Plugin.esp");
+    let master_path = PathBuf::from("./tests/assets/rename_interiors/Master.esm");
+
+    let options = MergeOptions {
+        rename_interiors: vec![("bar".into(), "Qux".into())],
+        ..OPTIONS
+    };
+    let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+
+    let renamed = output
+        .report
+        .renamed_interiors
+        .iter()
+        .map(|id| (id.record.as_str(), id.field.as_str(), id.old.as_str(), id.new.as_str()))
+        .sorted()
+        .collect_vec();
+    assert_eq!(
+        renamed,
+        [
+            ("CELL 'Hall' REFR 'door_bar' (0, 2)", "destination", "Bar", "Qux"),
+            ("INFO greeting (i1)", "speaker_cell", "Bar", "Qux"),
+            ("INFO greeting (i2)", "filters", "Bar", "Qux"),
+            // The travel destination, and the escort and follow packages.
+            ("NPC_ guide", "cell", "Bar", "Qux"),
+            ("NPC_ guide", "cell", "Bar", "Qux"),
+            ("NPC_ guide", "cell", "Bar", "Qux"),
+        ]
+    );
+
+    use tes3::esp::*;
+
+    let master = output.master;
+
+    let interior = master.cells.get_interior("Qux").unwrap();
+    assert_eq!(interior.cell.as_ref().unwrap().name, "Qux");
+    assert_eq!(interior.pathgrid.as_ref().unwrap().cell, "Qux");
+
+    let hall = master.cells.get_interior("Hall").unwrap().cell.as_ref().unwrap();
+    assert_eq!(hall.references[&(0, 2)].destination.as_ref().unwrap().cell, "Qux");
+
+    let Some(TES3Object::Npc(npc)) = master.objects.get(&(&[0; 4], "guide".to_owned())) else {
+        panic!("expected the npc");
+    };
+    assert_eq!(npc.travel_destinations[0].cell, "Qux");
+    assert!(matches!(
+        &npc.ai_packages[..],
+        [AiPackage::Escort(_), AiPackage::Follow(_)]
+    ));
+
+    let infos = &master.dialogues["greeting"].infos;
+    assert_eq!(infos[1].filters[0].id, "Qux");
+}
+
+#[test]
+fn flatten_masters_chain() {
+    let master_paths =
+        ["A.esm", "B.esm", "C.esm"].map(|name| PathBuf::from("./tests/assets/flatten_masters").join(name));
+
+    let flattened = flatten_masters(&master_paths).unwrap();
+    assert!(flattened.header.masters.is_empty());
+
+    // The reference of `B.esm` was renumbered when flattened, and the edit of `C.esm` follows it.
+    let hall = flattened.cells.get_interior("Hall").unwrap().cell.as_ref().unwrap();
+    let references = hall
+        .references
+        .iter()
+        .map(|(&key, reference)| (key, reference.id.as_str(), reference.translation[0]))
+        .sorted_by_key(|&(key, ..)| key)
+        .collect_vec();
+    assert_eq!(
+        references,
+        [
+            ((0, 1), "rock", 100.0),
+            ((0, 2), "vase", 300.0),
+            ((0, 3), "rock", 400.0)
+        ]
+    );
+}
+
+#[test]
+fn history() {
+    // Work on copies, as the master and its manifest are updated by each merge.
+    let root = std::env::temp_dir().join("merge_to_master_history");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).unwrap();
+
+    let plugin_path = root.join("Plugin.esp");
+    let master_path = root.join("Master.esm");
+    std::fs::copy("./tests/assets/remerge/Plugin.esp", &plugin_path).unwrap();
+    std::fs::copy("./tests/assets/remerge/Master.esm", &master_path).unwrap();
+
+    for _ in 0..2 {
+        let options = MergeOptions {
+            remerge: true,
+            annotate_header: true,
+            ..OPTIONS
+        };
+        let output = merge_plugins_with_report(&plugin_path, &master_path, options).unwrap();
+        output.master.save_path(&master_path).unwrap();
+
+        let mut manifest = MergeManifest::from_master_path(&master_path).unwrap();
+        manifest.entries.push(output.manifest_entry);
+        manifest.save_master_path(&master_path).unwrap();
+    }
+
+    let manifest = MergeManifest::from_master_path(&master_path).unwrap();
+    let [first, second] = &manifest.entries[..] else {
+        panic!("expected two entries");
+    };
+
+    // The reference of the first merge is replaced by that of the second.
+    assert_ne!(first.references, second.references);
+
+    for entry in [first, second] {
+        let history = entry.to_string();
+        let lines = history.lines().collect_vec();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].ends_with(&format!(
+            "  Plugin.esp  ({} bytes, hash {:016x})",
+            entry.size, entry.hash
+        )));
+        assert_eq!(lines[1], "    added 0 records and 1 references");
+        assert_eq!(lines[2], "    options: remerge, annotate_header");
+    }
+
+    // Both merges are noted in the header.
+    let master = PluginData::from_path(&master_path).unwrap();
+    let annotations = master.header.description.split("\r\n").collect_vec();
+    assert_eq!(annotations, [first.annotation(), second.annotation()]);
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
 // ---------------------------------------------------------------------------
 
 use std::collections::HashMap as _HashMap;